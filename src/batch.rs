@@ -0,0 +1,302 @@
+use crate::prelude::*;
+
+use crate::db;
+use crate::features::Recommender;
+use crate::observer::{self, ObserverKind, ObserverSpec};
+use crate::problem::*;
+use crate::registry::{self, Setup};
+use crate::solution::*;
+use crate::solver::{self, Cancel};
+use crate::tune::{SizeClass, SolverConfig};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Runs a solver over many problems with a fixed number of worker threads and
+// a total wall-clock budget, starting each problem from its current best. The
+// solver is SA, or the recommended one for each problem, see features.rs.
+
+// Nobody gets less than this, however small its share.
+const MIN_BUDGET: Duration = Duration::from_secs(1);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Weighting {
+    // musicians * attendees
+    Size,
+    // Distance from the best score to the tentative upper bound.
+    Gap,
+    // As recommended, see features.rs.
+    Recommended,
+}
+
+// "all", or a comma separated list of ids and ranges, e.g. "1-10,15".
+pub fn parse_ids(s: &str) -> Result<Vec<ProblemId>> {
+    if s == "all" {
+        return Ok((1..=MAX_PROMLEM_ID)
+            .filter(|id| project_path(format!("problem/{id}.json")).exists())
+            .collect());
+    }
+    let mut ids = BTreeSet::new();
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (from.parse::<ProblemId>()?, to.parse::<ProblemId>()?);
+                ensure!(from <= to, "invalid range: {part}");
+                ids.extend(from..=to);
+            }
+            None => {
+                ids.insert(part.parse::<ProblemId>()?);
+            }
+        }
+    }
+    Ok(ids.into_iter().collect())
+}
+
+struct Job {
+    id: ProblemId,
+    solver: &'static str,
+    old: Option<Score>,
+    weight: f64,
+    budget: Duration,
+}
+
+struct Outcome {
+    id: ProblemId,
+    solver: &'static str,
+    old: Option<Score>,
+    new: Result<Score>,
+    budget: Duration,
+}
+
+fn weight(problem: &Problem, old: Option<Score>, weighting: Weighting, recommended: f64) -> f64 {
+    match weighting {
+        Weighting::Size => (problem.musicians.len() * problem.attendees.len()) as f64,
+        Weighting::Gap => (problem.tentative_score() - old.unwrap_or(0.0)).max(0.0),
+        Weighting::Recommended => recommended,
+    }
+}
+
+// Splits `workers * budget` of solver time in proportion to the weights. No
+// job gets more than the whole budget, and what a capped job can't use goes
+// to the others, again by weight.
+fn allocate(jobs: &mut [Job], workers: usize, budget: Duration) {
+    let budget_secs = budget.as_secs_f64();
+    let mut pool = budget_secs * workers as f64;
+    let mut capped = vec![false; jobs.len()];
+    let mut shares = vec![0.0; jobs.len()];
+    loop {
+        let open = (0..jobs.len()).filter(|j| !capped[*j]).collect::<Vec<_>>();
+        let total = open.iter().map(|j| jobs[*j].weight).sum::<f64>();
+        for &j in &open {
+            // Equal shares if there is nothing to go by.
+            shares[j] = if total > 0.0 {
+                pool * jobs[j].weight / total
+            } else {
+                pool / open.len() as f64
+            };
+        }
+        let over = open
+            .into_iter()
+            .filter(|j| shares[*j] > budget_secs)
+            .collect::<Vec<_>>();
+        if over.is_empty() {
+            break;
+        }
+        for j in over {
+            capped[j] = true;
+            shares[j] = budget_secs;
+            pool -= budget_secs;
+        }
+    }
+    for (job, share) in jobs.iter_mut().zip(shares) {
+        job.budget = Duration::from_secs_f64(share).max(MIN_BUDGET).min(budget);
+    }
+}
+
+// Runs the job for its budget, or until `end` if that comes first.
+fn run(job: &Job, end: Instant, cancel: &Cancel) -> Result<Score> {
+    let remaining = end.saturating_duration_since(Instant::now());
+    let observers = observer::build(
+        &[
+            ObserverSpec::new(ObserverKind::Plot),
+            ObserverSpec::new(ObserverKind::Log),
+        ],
+        None,
+    )?;
+    let entry = registry::find(job.solver)?;
+    // Tuned for the size class, with the job's budget.
+    let class = SizeClass::of(&Problem::new(job.id)?);
+    let mut args = SolverConfig::load()?.params(job.solver, class);
+    args.push((
+        "end".to_string(),
+        format!("{}ms", job.budget.min(remaining).as_millis().max(1)),
+    ));
+    let setup = Setup {
+        initial_solution: Solution::best(job.id).ok(),
+        observers,
+    };
+    let solver = entry.build(job.id, &entry.params(&args)?, setup)?;
+    Ok(solver::solve(solver, remaining, cancel)?.score)
+}
+
+pub fn solve_all(
+    ids: &[ProblemId],
+    workers: usize,
+    budget: Duration,
+    weighting: Weighting,
+    recommend: bool,
+    cancel: &Cancel,
+) -> Result<()> {
+    ensure!(workers > 0, "workers must be positive");
+    let recommender = if recommend || weighting == Weighting::Recommended {
+        Some(Recommender::new()?)
+    } else {
+        None
+    };
+    let mut jobs = vec![];
+    for &id in ids {
+        let problem = Problem::new(id)?;
+        let old = db::score(id)?;
+        let recommendation = recommender
+            .as_ref()
+            .map(|r| r.recommend(id, &problem))
+            .transpose()?;
+        let recommended = recommendation.as_ref().map_or(0.0, |r| r.weight);
+        jobs.push(Job {
+            id,
+            solver: match &recommendation {
+                Some(r) if recommend => r.solver,
+                _ => "sa",
+            },
+            old,
+            weight: weight(&problem, old, weighting, recommended),
+            budget: Duration::ZERO,
+        });
+    }
+    allocate(&mut jobs, workers, budget);
+    let end = Instant::now() + budget;
+
+    // Longest first, so that the workers finish at about the same time.
+    jobs.sort_by_key(|j| std::cmp::Reverse(j.budget));
+    let queue = Mutex::new(jobs.iter().collect::<VecDeque<_>>());
+    let outcomes = Mutex::new(vec![]);
+
+    std::thread::scope(|s| {
+        for _ in 0..workers.min(jobs.len()) {
+            s.spawn(|| loop {
+                if cancel.is_cancelled() || Instant::now() >= end {
+                    break;
+                }
+                let Some(job) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                info!(
+                    "solve-all: {}, solver: {}, budget: {:?}",
+                    job.id, job.solver, job.budget
+                );
+                let new = run(job, end, cancel);
+                if let Err(e) = &new {
+                    error!("solve-all: {}: {e:?}", job.id);
+                }
+                outcomes.lock().unwrap().push(Outcome {
+                    id: job.id,
+                    solver: job.solver,
+                    old: job.old,
+                    new,
+                    budget: job.budget,
+                });
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    let left = queue.into_inner().unwrap().len();
+    if left > 0 {
+        warn!("solve-all: out of time with {left} problems left");
+    }
+    outcomes.sort_by_key(|o| o.id);
+    summary(&outcomes);
+    Ok(())
+}
+
+fn summary(outcomes: &[Outcome]) {
+    println!(
+        "{:>4} {:>8} {:>16} {:>16} {:>16} {:>8}",
+        "id", "solver", "old", "new", "diff", "budget"
+    );
+    let (mut old_total, mut new_total) = (0.0, 0.0);
+    for o in outcomes {
+        let old = o.old.unwrap_or(0.0);
+        old_total += old;
+        match &o.new {
+            Ok(new) => {
+                // Only improvements are kept as the best.
+                new_total += new.max(old);
+                println!(
+                    "{:>4} {:>8} {old:>16.0} {new:>16.0} {:>+16.0} {:>7.0}s",
+                    o.id,
+                    o.solver,
+                    new - old,
+                    o.budget.as_secs_f64()
+                );
+            }
+            Err(_) => {
+                new_total += old;
+                println!(
+                    "{:>4} {:>8} {old:>16.0} {:>16} {:>16} {:>7.0}s",
+                    o.id,
+                    o.solver,
+                    "error",
+                    "",
+                    o.budget.as_secs_f64()
+                );
+            }
+        }
+    }
+    println!(
+        "{:>4} {:>8} {old_total:>16.0} {new_total:>16.0} {:>+16.0}",
+        "all",
+        "",
+        new_total - old_total
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ids_test() -> Result<()> {
+        assert_eq!(parse_ids("3")?, [3]);
+        assert_eq!(parse_ids("1-3,7,2")?, [1, 2, 3, 7]);
+        assert!(parse_ids("3-1").is_err());
+        assert!(parse_ids("all")?.contains(&1));
+        Ok(())
+    }
+
+    #[test]
+    fn allocate_test() {
+        let job = |weight| Job {
+            id: 1,
+            solver: "sa",
+            old: None,
+            weight,
+            budget: Duration::ZERO,
+        };
+        let budget = Duration::from_secs(60);
+        for (weights, workers) in [
+            (vec![100.0, 1.0, 1.0], 3),
+            (vec![100.0, 1.0, 1.0, 2.0], 2),
+            (vec![1.0, 1.0], 4),
+            (vec![0.0, 0.0, 0.0], 2),
+        ] {
+            let mut jobs = weights.into_iter().map(job).collect::<Vec<_>>();
+            allocate(&mut jobs, workers, budget);
+            assert!(jobs.iter().all(|j| j.budget >= MIN_BUDGET && j.budget <= budget));
+            // What the capped jobs can't use isn't lost.
+            let total = jobs.iter().map(|j| j.budget.as_secs_f64()).sum::<f64>();
+            let expected = (budget.as_secs_f64() * workers as f64)
+                .min(budget.as_secs_f64() * jobs.len() as f64);
+            assert_relative_eq!(total, expected, max_relative = 1e-9);
+        }
+    }
+}
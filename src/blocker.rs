@@ -0,0 +1,133 @@
+use rand::rngs::StdRng;
+
+use crate::prelude::*;
+
+use crate::local_state::*;
+use rand::Rng;
+
+// Muted musicians don't play, but their bodies still block sight lines. This
+// phase moves them onto the sight lines between audible musicians and the
+// attendees who hate their instruments.
+
+const TARGET_REFRESH: usize = 100;
+const MAX_BLOCK_DISTANCE: Coord = 4.0 * MUSICIAN_RADIUS;
+
+struct Target {
+    musician: usize,
+    attendee: usize,
+    harm: Score,
+}
+
+fn targets(st: &LocalState) -> Vec<Target> {
+    (0..st.place.len())
+        .filter(|i| !st.is_muted(*i))
+        .flat_map(|musician| {
+            st.unblocked_haters(musician)
+                .into_iter()
+                .map(move |(attendee, harm)| Target {
+                    musician,
+                    attendee,
+                    harm,
+                })
+        })
+        .collect()
+}
+
+fn pick<'a>(targets: &'a [Target], total: Score, rng: &mut StdRng) -> &'a Target {
+    let mut r = rng.random_range(0.0..total);
+    for t in targets {
+        if r < t.harm {
+            return t;
+        }
+        r -= t.harm;
+    }
+    targets.last().unwrap()
+}
+
+// Hill climbs muted musicians' positions, and returns the score gained.
+pub fn optimize_blockers(st: &mut LocalState, rng: &mut StdRng, iterations: usize) -> Score {
+    let muted = (0..st.place.len())
+        .filter(|i| st.is_muted(*i))
+        .collect::<Vec<_>>();
+    if muted.is_empty() {
+        return 0.0;
+    }
+
+    // Moves are scored at the best volumes.
+    st.rebase_score();
+    let score = st.score;
+    let mut targets = vec![];
+    let mut total = 0.0;
+
+    for niter in 0..iterations {
+        if niter % TARGET_REFRESH == 0 {
+            targets = self::targets(st);
+            total = targets.iter().map(|t| t.harm).sum::<Score>();
+        }
+        if targets.is_empty() {
+            break;
+        }
+
+        // A point on the sight line, a little off it at most.
+        let target = pick(&targets, total, rng);
+        let from = st.place[target.musician];
+        let to = st.problem.attendees[target.attendee].point();
+        let d = from.distance(to);
+        let (ux, uy) = ((to.x - from.x) / d, (to.y - from.y) / d);
+        let t = rng.random_range(
+            MUSICIAN_RADIUS + 1e-6..MAX_BLOCK_DISTANCE.min(d).max(MUSICIAN_RADIUS + 1e-3),
+        );
+        let o = rng.random_range(-0.8 * BLOCK_RADIUS..0.8 * BLOCK_RADIUS);
+        let p = Point::new(from.x + t * ux - o * uy, from.y + t * uy + o * ux);
+
+        let b = muted[rng.random_range(0..muted.len())];
+        if st.collides(b, p) {
+            continue;
+        }
+        if st.delta_move(b, p) > 0.0 {
+            st.move_to(b, p);
+            st.commit();
+        }
+    }
+
+    let gain = st.score - score;
+    info!("blockers: muted: {}, gain: {gain:.1}", muted.len());
+    gain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problem::*;
+    use crate::solution::*;
+
+    #[test]
+    fn blocker_blocks_hater() -> Result<()> {
+        // Musician 0 is heard by someone who hates it, musician 1 is muted.
+        let mut problem = Problem::example()?;
+        problem.musicians = vec![0, 1];
+        problem.attendees = vec![crate::problem::Attendee {
+            x: 1000.0,
+            y: 500.0,
+            tastes: vec![-100.0, -100.0],
+        }];
+        problem.attendees.push(crate::problem::Attendee {
+            x: 600.0,
+            y: 500.0,
+            tastes: vec![1000.0, -1000.0],
+        });
+        let solution = Solution {
+            placements: vec![Point::new(1000.0, 100.0), Point::new(1400.0, 100.0)],
+            volumes: vec![10.0, 0.0],
+        };
+        let mut st = LocalState::new(&problem, 0, Spec::V1, &solution);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(st.is_muted(1));
+        assert_eq!(st.unblocked_haters(0).len(), 1);
+
+        let gain = optimize_blockers(&mut st, &mut rng, 100);
+        assert!(gain > 0.0);
+        assert!(st.unblocked_haters(0).is_empty());
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
 use crate::prelude::*;
 use crate::problem::*;
+use crate::solution::*;
 use rusqlite::OptionalExtension;
 use rusqlite::{named_params, Connection};
 use std::sync::LazyLock;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Userboard {
@@ -54,10 +56,69 @@ fn create_table(conn: &Connection) -> Result<usize> {
     )?)
 }
 
+// A few good and different solutions per problem, see elite.rs.
+fn create_elite_table(conn: &Connection) -> Result<usize> {
+    Ok(conn.execute(
+        "CREATE TABLE IF NOT EXISTS elite (
+            rowid    INTEGER PRIMARY KEY,
+            id       INTEGER NOT NULL,
+            score    REAL NOT NULL,
+            solution TEXT NOT NULL
+        ) STRICT",
+        (),
+    )?)
+}
+
+// Every solver::solve call. Times are in seconds since the Unix epoch.
+fn create_runs_table(conn: &Connection) -> Result<usize> {
+    Ok(conn.execute(
+        "CREATE TABLE IF NOT EXISTS runs (
+            rowid         INTEGER PRIMARY KEY,
+            id            INTEGER NOT NULL,
+            solver        TEXT NOT NULL,
+            params        TEXT NOT NULL,
+            seed          INTEGER NOT NULL,
+            start         REAL NOT NULL,
+            end           REAL NOT NULL,
+            iterations    INTEGER,
+            initial_score REAL,
+            score         REAL NOT NULL,
+            solution_path TEXT NOT NULL
+        ) STRICT",
+        (),
+    )?)
+}
+
+// Solution bodies by the hash of their content, see store.rs.
+fn create_solutions_table(conn: &Connection) -> Result<()> {
+    Ok(conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS solutions (
+            hash       TEXT PRIMARY KEY,
+            id         INTEGER NOT NULL,
+            score      REAL NOT NULL,
+            valid      INTEGER NOT NULL,
+            provenance TEXT NOT NULL,
+            created    REAL NOT NULL,
+            submitted  REAL,
+            body       BLOB NOT NULL
+        ) STRICT;
+        CREATE INDEX IF NOT EXISTS solutions_id ON solutions (id, score)",
+    )?)
+}
+
 fn db() -> std::sync::MutexGuard<'static, Connection> {
     static CONNECTION: LazyLock<Mutex<Connection>> = LazyLock::new(|| {
-        let conn = Connection::open(project_path("db.sqlite")).expect("open?");
+        // Tests get a database of their own, empty at the start.
+        let conn = if cfg!(test) {
+            Connection::open_in_memory()
+        } else {
+            Connection::open(project_path("db.sqlite"))
+        }
+        .expect("open?");
         create_table(&conn).expect("creata_table?");
+        create_elite_table(&conn).expect("create_elite_table?");
+        create_runs_table(&conn).expect("create_runs_table?");
+        create_solutions_table(&conn).expect("create_solutions_table?");
         Mutex::new(conn)
     });
     CONNECTION.lock().unwrap()
@@ -82,6 +143,229 @@ pub fn update_score(id: ProblemId, new_score: Score) -> Result<usize> {
     )?)
 }
 
+// The elite of a problem as (rowid, score, solution), best first.
+pub fn elite(id: ProblemId) -> Result<Vec<(i64, Score, Solution)>> {
+    let db = db();
+    let mut stmt =
+        db.prepare("SELECT rowid, score, solution FROM elite WHERE id = ?1 ORDER BY score DESC")?;
+    let rows = stmt.query_map((id,), |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?))
+    })?;
+    let mut elite = vec![];
+    for row in rows {
+        let (rowid, score, json) = row?;
+        elite.push((rowid, score, serde_json::from_str(&json)?));
+    }
+    Ok(elite)
+}
+
+pub fn insert_elite(id: ProblemId, score: Score, solution: &Solution) -> Result<usize> {
+    Ok(db().execute(
+        "INSERT INTO elite (id, score, solution) VALUES(:id, :score, :solution)",
+        named_params! {
+            ":id": id,
+            ":score": score,
+            ":solution": serde_json::to_string(solution)?,
+        },
+    )?)
+}
+
+pub fn delete_elite(rowid: i64) -> Result<usize> {
+    Ok(db().execute("DELETE FROM elite WHERE rowid = ?1", (rowid,))?)
+}
+
+// Seconds since the Unix epoch, as times are kept in the database.
+pub fn unix_time(t: SystemTime) -> f64 {
+    t.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub problem_id: ProblemId,
+    pub solver: String,
+    pub params: String,
+    pub seed: u64,
+    pub start: f64,
+    pub end: f64,
+    // None if the solver doesn't report progress.
+    pub iterations: Option<usize>,
+    pub initial_score: Option<Score>,
+    pub score: Score,
+    pub solution_path: String,
+}
+
+pub fn insert_run(run: &Run) -> Result<usize> {
+    Ok(db().execute(
+        "INSERT INTO runs (id, solver, params, seed, start, end, iterations, initial_score,
+  score, solution_path)
+  VALUES(:id, :solver, :params, :seed, :start, :end, :iterations, :initial_score, :score,
+  :solution_path)",
+        named_params! {
+            ":id": run.problem_id,
+            ":solver": run.solver,
+            ":params": run.params,
+            ":seed": run.seed,
+            ":start": run.start,
+            ":end": run.end,
+            ":iterations": run.iterations,
+            ":initial_score": run.initial_score,
+            ":score": run.score,
+            ":solution_path": run.solution_path,
+        },
+    )?)
+}
+
+// The runs on a problem, oldest first.
+pub fn runs(id: ProblemId) -> Result<Vec<Run>> {
+    let db = db();
+    let mut stmt = db.prepare(
+        "SELECT solver, params, seed, start, end, iterations, initial_score, score,
+  solution_path FROM runs WHERE id = ?1 ORDER BY start",
+    )?;
+    let rows = stmt.query_map((id,), |row| {
+        Ok(Run {
+            problem_id: id,
+            solver: row.get(0)?,
+            params: row.get(1)?,
+            seed: row.get(2)?,
+            start: row.get(3)?,
+            end: row.get(4)?,
+            iterations: row.get(5)?,
+            initial_score: row.get(6)?,
+            score: row.get(7)?,
+            solution_path: row.get(8)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// The runs on a problem as a table, the best marked.
+pub fn print_runs(id: ProblemId) -> Result<()> {
+    let best = score(id)?;
+    let db = db();
+    let mut stmt = db.prepare(
+        "SELECT datetime(start, 'unixepoch', 'localtime'), end - start, solver, params,
+  iterations, initial_score, score FROM runs WHERE id = ?1 ORDER BY start",
+    )?;
+    let mut rows = stmt.query((id,))?;
+    while let Some(row) = rows.next()? {
+        let start: String = row.get(0)?;
+        let seconds: f64 = row.get(1)?;
+        let solver: String = row.get(2)?;
+        let params: String = row.get(3)?;
+        let iterations: Option<usize> = row.get(4)?;
+        let initial_score: Option<Score> = row.get(5)?;
+        let score: Score = row.get(6)?;
+        let mark = if best.is_some_and(|best| score >= best) {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{start} {seconds:>8.1} {:>12} {:>16} {score:>16.1}{mark} {solver} {params}",
+            iterations.map_or("-".to_string(), |n| n.to_string()),
+            initial_score.map_or("-".to_string(), |s| format!("{s:.1}")),
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredSolution {
+    pub hash: String,
+    pub problem_id: ProblemId,
+    pub score: Score,
+    pub valid: bool,
+    // The solver, or where it was imported from.
+    pub provenance: String,
+    pub created: f64,
+    // When it was last submitted, if ever.
+    pub submitted: Option<f64>,
+    pub body: Vec<u8>,
+}
+
+const SOLUTION_COLUMNS: &str = "hash, id, score, valid, provenance, created, submitted, body";
+
+fn stored_solution(row: &rusqlite::Row) -> rusqlite::Result<StoredSolution> {
+    Ok(StoredSolution {
+        hash: row.get(0)?,
+        problem_id: row.get(1)?,
+        score: row.get(2)?,
+        valid: row.get(3)?,
+        provenance: row.get(4)?,
+        created: row.get(5)?,
+        submitted: row.get(6)?,
+        body: row.get(7)?,
+    })
+}
+
+// A solution already there keeps its provenance.
+pub fn insert_solution(solution: &StoredSolution) -> Result<usize> {
+    Ok(db().execute(
+        &format!(
+            "INSERT OR IGNORE INTO solutions ({SOLUTION_COLUMNS})
+  VALUES(:hash, :id, :score, :valid, :provenance, :created, :submitted, :body)"
+        ),
+        named_params! {
+            ":hash": solution.hash,
+            ":id": solution.problem_id,
+            ":score": solution.score,
+            ":valid": solution.valid,
+            ":provenance": solution.provenance,
+            ":created": solution.created,
+            ":submitted": solution.submitted,
+            ":body": solution.body,
+        },
+    )?)
+}
+
+pub fn solution(hash: &str) -> Result<Option<StoredSolution>> {
+    Ok(db()
+        .query_one(
+            &format!("SELECT {SOLUTION_COLUMNS} FROM solutions WHERE hash = ?1"),
+            (hash,),
+            stored_solution,
+        )
+        .optional()?)
+}
+
+// The valid solution with the best score.
+pub fn best_solution(id: ProblemId) -> Result<Option<StoredSolution>> {
+    Ok(db()
+        .query_one(
+            &format!(
+                "SELECT {SOLUTION_COLUMNS} FROM solutions WHERE id = ?1 AND valid
+  ORDER BY score DESC, created LIMIT 1"
+            ),
+            (id,),
+            stored_solution,
+        )
+        .optional()?)
+}
+
+// The solution submitted last.
+pub fn submitted_solution(id: ProblemId) -> Result<Option<StoredSolution>> {
+    Ok(db()
+        .query_one(
+            &format!(
+                "SELECT {SOLUTION_COLUMNS} FROM solutions WHERE id = ?1 AND submitted IS NOT NULL
+  ORDER BY submitted DESC LIMIT 1"
+            ),
+            (id,),
+            stored_solution,
+        )
+        .optional()?)
+}
+
+pub fn mark_submitted(hash: &str, time: f64) -> Result<usize> {
+    Ok(db().execute(
+        "UPDATE solutions SET submitted = ?2 WHERE hash = ?1",
+        (hash, time),
+    )?)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -104,4 +388,29 @@ mod tests {
         assert_eq!(score(10000)?, None);
         Ok(())
     }
+
+    #[test]
+    fn runs_test() -> Result<()> {
+        // Problem 0 is the example.
+        assert!(runs(0)?.is_empty());
+        let run = Run {
+            problem_id: 0,
+            solver: "test".to_string(),
+            params: "end=1".to_string(),
+            seed: 0,
+            start: 1.0,
+            end: 2.0,
+            iterations: None,
+            initial_score: Some(1.0),
+            score: 2.0,
+            solution_path: "solution/all/0-test-2.json".to_string(),
+        };
+        insert_run(&run)?;
+        let runs = runs(0)?;
+        assert_eq!(runs.len(), 1);
+        assert!(runs
+            .iter()
+            .any(|r| r.solver == "test" && r.iterations.is_none()));
+        Ok(())
+    }
 }
@@ -1,6 +1,7 @@
 use crate::prelude::*;
 use std::f64::consts::PI;
 
+use crate::pillars::{self, ShadowMap};
 use crate::problem::*;
 use crate::solution::*;
 use cairo::SvgSurface;
@@ -117,3 +118,55 @@ pub fn draw_solution(id: ProblemId, solution: &Solution, out_path: impl AsRef<Pa
     let problem = Problem::new(id)?;
     draw_svg(&problem, Some(solution), out_path)
 }
+
+// Attendees whose pillar shadows are drawn, the most valuable first.
+const SHADOW_ATTENDEES: usize = 10;
+
+// How clear the sight lines are on the stage, from red (hidden) to clear, and
+// the shadows the pillars cast for the attendees with the highest tastes.
+pub fn draw_shadows_on_context(cr: &Context, problem: &Problem, map: &ShadowMap) -> Result<()> {
+    for (p, side, clear) in map.cells() {
+        cr.set_source_rgba(1.0, 0.0, 0.0, 0.6 * (1.0 - clear));
+        cr.rectangle(p.x, p.y, side, side);
+        cr.fill()?;
+    }
+
+    let reach = problem.room_width.hypot(problem.room_height);
+    let mut attendees = problem.attendees.iter().collect::<Vec<_>>();
+    attendees.sort_by_key(|a| std::cmp::Reverse(OrderedFloat(a.taste_max())));
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.1);
+    for a in attendees.into_iter().take(SHADOW_ATTENDEES) {
+        for pillar in &problem.pillars {
+            let Some(corners) = pillars::shadow(a.point(), pillar, reach) else {
+                continue;
+            };
+            cr.move_to(corners[0].x, corners[0].y);
+            for c in &corners[1..] {
+                cr.line_to(c.x, c.y);
+            }
+            cr.close_path();
+            cr.fill()?;
+        }
+    }
+    Ok(())
+}
+
+pub fn draw_shadows(
+    id: ProblemId,
+    solution_path: Option<impl AsRef<Path>>,
+    out_path: impl AsRef<Path>,
+) -> Result<()> {
+    let problem = Problem::new(id)?;
+    let solution = solution_path.map(Solution::from).transpose()?;
+
+    std::fs::create_dir_all(out_path.as_ref().parent().unwrap())?;
+    let surface = SvgSurface::new(
+        problem.room_width,
+        problem.room_height,
+        Some(out_path.as_ref()),
+    )?;
+    let cr = Context::new(&surface)?;
+    draw_svg_on_context(&cr, &problem, solution.as_ref())?;
+    draw_shadows_on_context(&cr, &problem, &ShadowMap::new(&problem))?;
+    Ok(())
+}
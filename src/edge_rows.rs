@@ -0,0 +1,215 @@
+use crate::prelude::*;
+
+use crate::local_state::*;
+use crate::pillars;
+use crate::problem::*;
+use crate::solution::*;
+
+// Front rows along the stage edges. Each edge is a 1-D problem: pick
+// positions at least MUSICIAN_RADIUS apart to maximize the summed impact of
+// the instruments played there. A DP over discretised positions picks them,
+// valuing each position at its best instrument still available, whatever the
+// number of musicians left for it. That makes its value a heuristic upper
+// bound, and the assignment of instruments afterwards can leave some picked
+// positions unused. Edges are filled best first, and the musicians left over
+// are placed inside for the solvers to move.
+
+// Positions along an edge are this far apart, so that DIVISIONS of them make
+// the minimum distance between musicians.
+const DIVISIONS: usize = 4;
+const STEP: Coord = (MUSICIAN_RADIUS + 1e-6) / DIVISIONS as Coord;
+
+struct Edge {
+    positions: Vec<Point>,
+}
+
+impl Edge {
+    fn new(from: Point, to: Point) -> Edge {
+        let len = from.distance(to);
+        let n = (len / STEP).floor() as usize + 1;
+        let positions = (0..n)
+            .map(|k| {
+                let t = if len > 0.0 {
+                    k as Coord * STEP / len
+                } else {
+                    0.0
+                };
+                Point::new(from.x + t * (to.x - from.x), from.y + t * (to.y - from.y))
+            })
+            .collect();
+        Edge { positions }
+    }
+}
+
+fn edges(problem: &Problem) -> Vec<Edge> {
+    let minx = problem.stage_bottom_left[0] + MUSICIAN_RADIUS;
+    let maxx = problem.stage_bottom_left[0] + problem.stage_width - MUSICIAN_RADIUS;
+    let miny = problem.stage_bottom_left[1] + MUSICIAN_RADIUS;
+    let maxy = problem.stage_bottom_left[1] + problem.stage_height - MUSICIAN_RADIUS;
+    vec![
+        Edge::new(Point::new(minx, miny), Point::new(maxx, miny)),
+        Edge::new(Point::new(maxx, miny), Point::new(maxx, maxy)),
+        Edge::new(Point::new(minx, maxy), Point::new(maxx, maxy)),
+        Edge::new(Point::new(minx, miny), Point::new(minx, maxy)),
+    ]
+}
+
+// At most k of the weights, no two closer than DIVISIONS indices, with the
+// largest sum. Only positive weights are taken. Returns the indices.
+pub fn best_row(weights: &[Score], k: usize) -> (Score, Vec<usize>) {
+    let n = weights.len();
+    // best[j][m]: the best sum over the first j positions with m picks.
+    let mut best = vec![vec![0.0; k + 1]; n + 1];
+    for j in 1..=n {
+        for m in 0..=k {
+            best[j][m] = best[j - 1][m];
+            if m > 0 && weights[j - 1] > 0.0 {
+                let take = weights[j - 1] + best[j.saturating_sub(DIVISIONS)][m - 1];
+                if take > best[j][m] {
+                    best[j][m] = take;
+                }
+            }
+        }
+    }
+
+    let mut picks = vec![];
+    let (mut j, mut m) = (n, k);
+    while j > 0 && m > 0 {
+        if best[j][m] == best[j - 1][m] {
+            j -= 1;
+        } else {
+            picks.push(j - 1);
+            j = j.saturating_sub(DIVISIONS);
+            m -= 1;
+        }
+    }
+    picks.reverse();
+    (best[n][k], picks)
+}
+
+pub fn edge_rows(problem: &Problem) -> Option<Solution> {
+    let nm = problem.musicians.len();
+    let mut counts = inst_cnt(&problem.musicians);
+    let mut placed: Vec<(Instrument, Point)> = vec![];
+
+    let mut edges = edges(problem)
+        .into_iter()
+        .map(|edge| {
+            // Positions in a pillar's shadow are worth less.
+            let at_positions = edge
+                .positions
+                .iter()
+                .map(|p| pillars::impacts(problem, *p))
+                .collect::<Vec<_>>();
+            let impacts = counts
+                .keys()
+                .map(|inst| (*inst, at_positions.iter().map(|v| v[*inst]).collect()))
+                .collect::<HashMap<_, Vec<_>>>();
+            (edge, impacts)
+        })
+        .collect::<Vec<_>>();
+
+    while !edges.is_empty() && placed.len() < nm {
+        // Each position is worth its best instrument still available, and
+        // nothing next to a musician on another edge. Counts are ignored, so
+        // several positions may count the same last musician.
+        let weights = |(edge, impacts): &(Edge, HashMap<Instrument, Vec<Score>>)| {
+            (0..edge.positions.len())
+                .map(|j| {
+                    let p = edge.positions[j];
+                    if placed
+                        .iter()
+                        .any(|(_, q)| p.distance_squared(*q) < MUSICIAN_RADIUS_2 + EPS)
+                    {
+                        return 0.0;
+                    }
+                    counts
+                        .iter()
+                        .filter(|(_, n)| **n > 0)
+                        .map(|(inst, _)| impacts[inst][j])
+                        .fold(0.0, Score::max)
+                })
+                .collect::<Vec<_>>()
+        };
+        let (e, (value, picks)) = edges
+            .iter()
+            .map(|edge| best_row(&weights(edge), nm - placed.len()))
+            .enumerate()
+            .max_by_key(|(_, (value, _))| OrderedFloat(*value))?;
+        if value <= 0.0 {
+            break;
+        }
+        let (edge, impacts) = edges.swap_remove(e);
+
+        // The most valuable (position, instrument) pairs first.
+        let mut pairs = picks
+            .iter()
+            .flat_map(|j| impacts.iter().map(move |(inst, v)| (v[*j], *j, *inst)))
+            .filter(|(v, _, _)| *v > 0.0)
+            .collect::<Vec<_>>();
+        pairs.sort_by_key(|(v, _, _)| std::cmp::Reverse(OrderedFloat(*v)));
+        let mut taken = HashSet::new();
+        for (_, j, inst) in pairs {
+            if counts[&inst] > 0 && taken.insert(j) {
+                *counts.get_mut(&inst).unwrap() -= 1;
+                placed.push((inst, edge.positions[j]));
+            }
+        }
+        debug!("edge rows: value: {value:.3}, placed: {}", placed.len());
+    }
+
+    // Everyone else goes to the free lattice slots, in lattice order.
+    let mut interior = candidate_slots(problem)
+        .into_iter()
+        .filter(|p| {
+            placed
+                .iter()
+                .all(|(_, q)| p.distance_squared(*q) >= MUSICIAN_RADIUS_2 + EPS)
+        })
+        .collect::<Vec<_>>();
+    interior.reverse();
+    let mut rest = counts.into_iter().collect::<Vec<_>>();
+    rest.sort();
+    for (inst, n) in rest {
+        for _ in 0..n {
+            placed.push((inst, interior.pop()?));
+        }
+    }
+
+    // Musicians of an instrument are interchangeable.
+    let mut by_inst = HashMap::<Instrument, Vec<Point>>::new();
+    for (inst, p) in placed {
+        by_inst.entry(inst).or_default().push(p);
+    }
+    let placements = problem
+        .musicians
+        .iter()
+        .map(|inst| by_inst.get_mut(inst).unwrap().pop().unwrap())
+        .collect();
+    let solution = Solution {
+        placements,
+        volumes: vec![10.0; nm],
+    };
+    solution.validate(problem).ok()?;
+    Some(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_row_test() {
+        // Two picks can't be neighbours within DIVISIONS.
+        let mut weights = vec![0.0; 10];
+        weights[2] = 5.0;
+        weights[3] = 4.0;
+        weights[7] = 3.0;
+        assert_eq!(best_row(&weights, 2), (8.0, vec![2, 7]));
+        assert_eq!(best_row(&weights, 1), (5.0, vec![2]));
+
+        let problem = Problem::example().unwrap();
+        let solution = edge_rows(&problem).unwrap();
+        assert!(solution.validate(&problem).is_ok());
+    }
+}
@@ -0,0 +1,266 @@
+use crate::prelude::*;
+
+use crate::db;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver_sa;
+use crate::store;
+
+// A pool of good and different solutions per problem, kept in the database.
+// Two solutions are as far apart as the mean distance a musician moves under
+// the best matching of one's musicians to the other's, where only musicians
+// of the same instrument can be matched. A solution too close to a member
+// replaces it if better, or is dropped. Otherwise it joins the pool, or
+// replaces the worst member once the pool is full and it is better.
+
+pub const POOL_SIZE: usize = 8;
+// Closer than this on average, two solutions are the same one.
+const MIN_DISTANCE: Coord = MUSICIAN_RADIUS;
+
+// The column of each row in a minimum-cost assignment of a square matrix.
+fn hungarian(cost: &[Vec<f64>]) -> Vec<usize> {
+    let n = cost.len();
+    // 1-based, with row and column 0 as sentinels.
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut row_of = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+                let c = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                if c < minv[j] {
+                    minv[j] = c;
+                    way[j] = j0;
+                }
+                if minv[j] < delta {
+                    delta = minv[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+        while j0 != 0 {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+        }
+    }
+    let mut col_of = vec![0; n];
+    for j in 1..=n {
+        col_of[row_of[j] - 1] = j - 1;
+    }
+    col_of
+}
+
+pub fn distance(problem: &Problem, a: &Solution, b: &Solution) -> Coord {
+    let mut classes = HashMap::<Instrument, Vec<usize>>::new();
+    for (i, inst) in problem.musicians.iter().enumerate() {
+        classes.entry(*inst).or_default().push(i);
+    }
+    let mut total = 0.0;
+    for musicians in classes.values() {
+        let cost = musicians
+            .iter()
+            .map(|i| {
+                musicians
+                    .iter()
+                    .map(|j| a.placements[*i].distance(b.placements[*j]))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let matching = hungarian(&cost);
+        total += matching
+            .iter()
+            .enumerate()
+            .map(|(i, j)| cost[i][*j])
+            .sum::<f64>();
+    }
+    total / problem.musicians.len().max(1) as Coord
+}
+
+#[derive(Debug, PartialEq)]
+enum Admission {
+    Rejected,
+    Added,
+    // The index of the member it replaces.
+    Replaces(usize),
+}
+
+fn admit(
+    problem: &Problem,
+    pool: &[(Score, &Solution)],
+    score: Score,
+    solution: &Solution,
+) -> Admission {
+    let near = pool
+        .iter()
+        .enumerate()
+        .map(|(k, (s, member))| (k, *s, distance(problem, solution, member)))
+        .filter(|(_, _, d)| *d < MIN_DISTANCE)
+        .collect::<Vec<_>>();
+    if !near.is_empty() {
+        if near.iter().any(|(_, s, _)| *s >= score) {
+            return Admission::Rejected;
+        }
+        let nearest = near
+            .iter()
+            .min_by_key(|(_, _, d)| OrderedFloat(*d))
+            .unwrap();
+        return Admission::Replaces(nearest.0);
+    }
+    if pool.len() < POOL_SIZE {
+        return Admission::Added;
+    }
+    match pool
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, (s, _))| OrderedFloat(*s))
+    {
+        Some((k, (worst, _))) if *worst < score => Admission::Replaces(k),
+        _ => Admission::Rejected,
+    }
+}
+
+// Whether the solution made it into the pool.
+pub fn offer(
+    problem_id: ProblemId,
+    problem: &Problem,
+    score: Score,
+    solution: &Solution,
+) -> Result<bool> {
+    let elite = db::elite(problem_id)?;
+    let pool = elite.iter().map(|(_, s, m)| (*s, m)).collect::<Vec<_>>();
+    match admit(problem, &pool, score, solution) {
+        Admission::Rejected => return Ok(false),
+        Admission::Added => {}
+        Admission::Replaces(k) => {
+            db::delete_elite(elite[k].0)?;
+        }
+    }
+    db::insert_elite(problem_id, score, solution)?;
+    info!("elite: {problem_id}: {score:.1} joins");
+    Ok(true)
+}
+
+// Best first.
+pub fn pool(problem_id: ProblemId) -> Result<Vec<(Score, Solution)>> {
+    Ok(db::elite(problem_id)?
+        .into_iter()
+        .map(|(_, score, solution)| (score, solution))
+        .collect())
+}
+
+// A member picked uniformly, if there is any.
+pub fn sample(problem_id: ProblemId, rng: &mut StdRng) -> Result<Option<Solution>> {
+    let mut pool = pool(problem_id)?;
+    if pool.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(pool.swap_remove(rng.random_range(0..pool.len())).1))
+}
+
+// Offers the valid solutions in solution/all, rescored.
+pub fn import(problem_id: ProblemId) -> Result<()> {
+    let problem = Problem::new(problem_id)?;
+    for (name, _, path) in Solution::stored(problem_id)? {
+        let solution = match store::read(&path) {
+            Ok(solution) => solution,
+            Err(e) => {
+                warn!("elite: {e:?}");
+                continue;
+            }
+        };
+        if let Err(e) = solution.validate(&problem) {
+            warn!("elite: {}: {e:?}", path.display());
+            continue;
+        }
+        let score = solver_sa::score(&problem, problem_id, problem_id.into(), &solution);
+        if offer(problem_id, &problem, score, &solution)? {
+            println!("{problem_id}: {name}: {score:.1}");
+        }
+    }
+    Ok(())
+}
+
+// The members with their distance to the nearest other member.
+pub fn print(problem_id: ProblemId) -> Result<()> {
+    let problem = Problem::new(problem_id)?;
+    let pool = pool(problem_id)?;
+    for (k, (score, solution)) in pool.iter().enumerate() {
+        let nearest = pool
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != k)
+            .map(|(_, (_, other))| distance(&problem, solution, other))
+            .min_by_key(|d| OrderedFloat(*d));
+        match nearest {
+            Some(d) => println!("{problem_id} {score:>16.1} {d:>8.1}"),
+            None => println!("{problem_id} {score:>16.1}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hungarian_assignment() {
+        let cost = vec![
+            vec![4.0, 1.0, 3.0],
+            vec![2.0, 0.0, 5.0],
+            vec![3.0, 2.0, 2.0],
+        ];
+        assert_eq!(hungarian(&cost), [1, 0, 2]);
+    }
+
+    #[test]
+    fn pool_keeps_diverse() -> Result<()> {
+        let problem = Problem::example()?;
+        let a = Solution::example()?;
+        // Two musicians of the same instrument trading places.
+        let mut swapped = a.clone();
+        swapped.placements.swap(0, 2);
+        assert_eq!(problem.musicians[0], problem.musicians[2]);
+        assert_relative_eq!(distance(&problem, &a, &swapped), 0.0);
+
+        let mut b = a.clone();
+        for p in &mut b.placements {
+            p.y += 3.0 * MUSICIAN_RADIUS;
+        }
+        assert_relative_eq!(distance(&problem, &a, &b), 3.0 * MUSICIAN_RADIUS);
+
+        let pool = [(10.0, &a)];
+        assert_eq!(admit(&problem, &pool, 5.0, &swapped), Admission::Rejected);
+        assert_eq!(
+            admit(&problem, &pool, 20.0, &swapped),
+            Admission::Replaces(0)
+        );
+        assert_eq!(admit(&problem, &pool, 5.0, &b), Admission::Added);
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use crate::prelude::*;
+
+use crate::db;
+use crate::problem::*;
+use crate::registry;
+
+// What a problem looks like, and which solver to give how much time. A problem
+// that several solvers have been run on gets the one that did best there.
+// Otherwise the nearest problems by features that have runs vote for the
+// solver that did best on them. The time goes by size and by how far the best
+// score is from the upper bound.
+
+// Problems that vote.
+const NEIGHBORS: usize = 3;
+// Solvers run on a problem before its own history decides.
+const MIN_FAMILIES: usize = 2;
+const DEFAULT_SOLVER: &str = "sa";
+// Even a problem at its bound keeps some time.
+const MIN_HEADROOM: f64 = 0.1;
+
+#[derive(Debug, Clone)]
+pub struct Features {
+    pub musicians: usize,
+    pub attendees: usize,
+    pub instruments: usize,
+    pub pillars: usize,
+    // The longer side of the stage over the shorter.
+    pub stage_aspect: f64,
+    // The share of positive tastes.
+    pub positive_tastes: f64,
+    // Pillar area over room area.
+    pub pillar_density: f64,
+    // Quartiles of the attendees' distances to the stage.
+    pub distance_quartiles: [Coord; 3],
+}
+
+impl Features {
+    pub fn new(problem: &Problem) -> Features {
+        let tastes = problem.attendees.iter().flat_map(|a| &a.tastes);
+        let ntaste = tastes.clone().count().max(1);
+        let positive = tastes.filter(|t| **t > 0.0).count();
+        let pillar_area = problem
+            .pillars
+            .iter()
+            .map(|p| std::f64::consts::PI * p.radius * p.radius)
+            .sum::<f64>();
+
+        let mut distances = problem
+            .attendees
+            .iter()
+            .map(|a| problem.distance_to_stage_squared(a.point()).sqrt())
+            .collect::<Vec<_>>();
+        distances.sort_by_key(|d| OrderedFloat(*d));
+        let quartile = |q: usize| {
+            distances
+                .get(distances.len() * q / 4)
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        Features {
+            musicians: problem.musicians.len(),
+            attendees: problem.attendees.len(),
+            instruments: inst_cnt(&problem.musicians).len(),
+            pillars: problem.pillars.len(),
+            stage_aspect: problem.stage_width.max(problem.stage_height)
+                / problem.stage_width.min(problem.stage_height),
+            positive_tastes: positive as f64 / ntaste as f64,
+            pillar_density: pillar_area / (problem.room_width * problem.room_height),
+            distance_quartiles: [quartile(1), quartile(2), quartile(3)],
+        }
+    }
+
+    // Counts and lengths on a log scale, shares as they are.
+    fn vector(&self) -> [f64; 8] {
+        let ln = |x: f64| (1.0 + x).ln();
+        [
+            ln(self.musicians as f64),
+            ln(self.attendees as f64),
+            ln(self.instruments as f64),
+            ln(self.pillars as f64),
+            self.stage_aspect.ln(),
+            self.positive_tastes,
+            100.0 * self.pillar_density,
+            ln(self.distance_quartiles[1]),
+        ]
+    }
+
+    pub fn distance(&self, other: &Features) -> f64 {
+        self.vector()
+            .iter()
+            .zip(other.vector())
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+// The solvers the batch runner can give a time budget, that is, with `end`.
+fn batch_solvers() -> impl Iterator<Item = &'static str> {
+    registry::SOLVERS
+        .iter()
+        .filter(|e| e.params.iter().any(|p| p.name == "end"))
+        .map(|e| e.name)
+}
+
+// The best score of each solver in the runs table, e.g. "sa" for
+// "sa-temp0-100-duration-60".
+pub fn history(id: ProblemId) -> Result<HashMap<&'static str, Score>> {
+    let mut best = HashMap::new();
+    for run in db::runs(id)? {
+        let family = run.solver.split('-').next().unwrap_or_default();
+        if let Some(solver) = batch_solvers().find(|s| *s == family) {
+            let entry = best.entry(solver).or_insert(run.score);
+            *entry = run.score.max(*entry);
+        }
+    }
+    Ok(best)
+}
+
+fn best_solver(history: &HashMap<&'static str, Score>) -> Option<&'static str> {
+    history
+        .iter()
+        .max_by_key(|(_, score)| OrderedFloat(**score))
+        .map(|(solver, _)| *solver)
+}
+
+#[derive(Debug, Clone)]
+pub struct Recommendation {
+    pub solver: &'static str,
+    // Relative share of the time.
+    pub weight: f64,
+}
+
+pub struct Recommender {
+    problems: Vec<(ProblemId, Features, HashMap<&'static str, Score>)>,
+}
+
+impl Recommender {
+    // Features and history of every problem there is.
+    pub fn new() -> Result<Recommender> {
+        let mut problems = vec![];
+        for id in 1..=MAX_PROMLEM_ID {
+            let Ok(problem) = Problem::new(id) else {
+                continue;
+            };
+            problems.push((id, Features::new(&problem), history(id)?));
+        }
+        Ok(Recommender { problems })
+    }
+
+    fn solver(&self, id: ProblemId, features: &Features) -> &'static str {
+        let own = self.problems.iter().find(|(i, _, _)| *i == id);
+        if let Some((_, _, history)) = own.filter(|(_, _, h)| h.len() >= MIN_FAMILIES) {
+            return best_solver(history).unwrap();
+        }
+        let mut neighbors = self
+            .problems
+            .iter()
+            .filter(|(i, _, history)| *i != id && !history.is_empty())
+            .map(|(_, f, history)| (features.distance(f), best_solver(history).unwrap()))
+            .collect::<Vec<_>>();
+        neighbors.sort_by_key(|(d, _)| OrderedFloat(*d));
+        let mut votes = HashMap::<&'static str, f64>::new();
+        for (d, solver) in neighbors.into_iter().take(NEIGHBORS) {
+            *votes.entry(solver).or_default() += 1.0 / (d + 1e-9);
+        }
+        votes
+            .into_iter()
+            .max_by_key(|(solver, v)| (OrderedFloat(*v), std::cmp::Reverse(*solver)))
+            .map_or(DEFAULT_SOLVER, |(solver, _)| solver)
+    }
+
+    pub fn recommend(&self, id: ProblemId, problem: &Problem) -> Result<Recommendation> {
+        let features = Features::new(problem);
+        let bound = problem.tentative_score();
+        let headroom = match db::score(id)? {
+            Some(best) if bound > 0.0 => (1.0 - best / bound).clamp(MIN_HEADROOM, 1.0),
+            _ => 1.0,
+        };
+        Ok(Recommendation {
+            solver: self.solver(id, &features),
+            weight: (features.musicians * features.attendees) as f64 * headroom,
+        })
+    }
+}
+
+// Features and recommendations as a table.
+pub fn print(ids: &[ProblemId]) -> Result<()> {
+    let recommender = Recommender::new()?;
+    println!(
+        "{:>4} {:>5} {:>6} {:>4} {:>5} {:>6} {:>6} {:>8} {:>8} {:>16}",
+        "id", "m", "a", "inst", "pill", "aspect", "pos", "dist", "solver", "weight"
+    );
+    for &id in ids {
+        let problem = Problem::new(id)?;
+        let f = Features::new(&problem);
+        let r = recommender.recommend(id, &problem)?;
+        println!(
+            "{id:>4} {:>5} {:>6} {:>4} {:>5} {:>6.2} {:>6.2} {:>8.0} {:>8} {:>16.0}",
+            f.musicians,
+            f.attendees,
+            f.instruments,
+            f.pillars,
+            f.stage_aspect,
+            f.positive_tastes,
+            f.distance_quartiles[1],
+            r.solver,
+            r.weight
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_features() -> Result<()> {
+        let problem = Problem::example()?;
+        let f = Features::new(&problem);
+        assert_eq!(
+            (f.musicians, f.attendees, f.instruments, f.pillars),
+            (3, 3, 2, 1)
+        );
+        assert_eq!(f.stage_aspect, 5.0);
+        assert_eq!(f.positive_tastes, 5.0 / 6.0);
+        assert_eq!(f.distance(&f), 0.0);
+        Ok(())
+    }
+}
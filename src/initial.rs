@@ -0,0 +1,199 @@
+use crate::prelude::*;
+
+use crate::edge_rows::edge_rows;
+use crate::elite;
+use crate::local_state::*;
+use crate::pillars;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver_sa::SolverSa;
+use crate::transfer;
+
+// Starting points for the solvers. Under Spec::V2, musicians of the same
+// instrument play louder the closer they stand, so `Clusters` packs each
+// instrument into one hex cluster where its audience is. `EdgeRows` fills the
+// front rows along the stage edges, see edge_rows.rs. Both value positions
+// by the attendees they reach past the pillars. `Transfer` takes the best
+// solution of a problem with the same stage, see transfer.rs, if it beats the
+// random start. `Elite` restarts from a random member of the problem's elite
+// pool, see elite.rs.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum InitialStrategy {
+    #[display("random")]
+    Random,
+    #[display("clusters")]
+    Clusters,
+    #[display("edges")]
+    EdgeRows,
+    #[display("transfer")]
+    Transfer,
+    #[display("elite")]
+    Elite,
+}
+
+impl std::str::FromStr for InitialStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "random" => InitialStrategy::Random,
+            "clusters" => InitialStrategy::Clusters,
+            "edges" => InitialStrategy::EdgeRows,
+            "transfer" => InitialStrategy::Transfer,
+            "elite" => InitialStrategy::Elite,
+            _ => bail!("unknown initial strategy: {s}"),
+        })
+    }
+}
+
+// At most this many lattice slots are considered as cluster centres.
+const MAX_CENTERS: usize = 400;
+
+pub fn initial_solution(
+    strategy: InitialStrategy,
+    problem: &Problem,
+    problem_id: ProblemId,
+    spec: Spec,
+    seed: u64,
+) -> Solution {
+    match strategy {
+        InitialStrategy::Random => SolverSa::initial_solution(problem),
+        InitialStrategy::Clusters => clusters(problem, problem_id, spec)
+            .unwrap_or_else(|| SolverSa::initial_solution(problem)),
+        InitialStrategy::EdgeRows => {
+            edge_rows(problem).unwrap_or_else(|| SolverSa::initial_solution(problem))
+        }
+        InitialStrategy::Transfer => {
+            let random = SolverSa::initial_solution(problem);
+            let random_score = LocalState::new(problem, problem_id, spec, &random).score;
+            match transfer::best_transfer(problem, problem_id, &mut StdRng::seed_from_u64(seed)) {
+                Ok(Some((score, source_id, solution))) if score > random_score => {
+                    info!("transfer: from {source_id}: {score:.1} > random: {random_score:.1}");
+                    solution
+                }
+                Ok(_) => random,
+                Err(e) => {
+                    warn!("transfer: {e:?}");
+                    random
+                }
+            }
+        }
+        InitialStrategy::Elite => {
+            let mut rng = StdRng::seed_from_u64(seed);
+            match elite::sample(problem_id, &mut rng) {
+                Ok(Some(solution)) => solution,
+                Ok(None) => SolverSa::initial_solution(problem),
+                Err(e) => {
+                    warn!("elite: {e:?}");
+                    SolverSa::initial_solution(problem)
+                }
+            }
+        }
+    }
+}
+
+// Fills the instruments in order, each into the free slots nearest to its best
+// free centre. None if the lattice is too small.
+fn pack(
+    problem: &Problem,
+    slots: &[Point],
+    centers: &[usize],
+    impacts: &HashMap<Instrument, Vec<Score>>,
+    order: &[Instrument],
+) -> Option<Solution> {
+    let mut free = vec![true; slots.len()];
+    let mut placements = vec![Point::new(0.0, 0.0); problem.musicians.len()];
+    for &inst in order {
+        let center = centers
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| free[**s])
+            .max_by_key(|(k, _)| OrderedFloat(impacts[&inst][*k]))
+            .map(|(_, s)| slots[*s])?;
+        let mut nearest = (0..slots.len()).filter(|s| free[*s]).collect::<Vec<_>>();
+        nearest.sort_by_key(|s| OrderedFloat(slots[*s].distance_squared(center)));
+        let musicians = (0..problem.musicians.len()).filter(|i| problem.musicians[*i] == inst);
+        for (i, s) in musicians.zip(nearest) {
+            placements[i] = slots[s];
+            free[s] = false;
+        }
+    }
+    let solution = Solution {
+        placements,
+        volumes: vec![10.0; problem.musicians.len()],
+    };
+    solution.validate(problem).ok()?;
+    Some(solution)
+}
+
+// Tries a few instrument orders and keeps the best under the spec's scorer.
+pub fn clusters(problem: &Problem, problem_id: ProblemId, spec: Spec) -> Option<Solution> {
+    let slots = candidate_slots(problem);
+    if slots.len() < problem.musicians.len() {
+        return None;
+    }
+    let stride = slots.len().div_ceil(MAX_CENTERS);
+    let centers = (0..slots.len()).step_by(stride).collect::<Vec<_>>();
+
+    let counts = inst_cnt(&problem.musicians);
+    // Centres in a pillar's shadow are worth less.
+    let at_centers = centers
+        .iter()
+        .map(|s| pillars::impacts(problem, slots[*s]))
+        .collect::<Vec<_>>();
+    let impacts = counts
+        .keys()
+        .map(|inst| (*inst, at_centers.iter().map(|v| v[*inst]).collect()))
+        .collect::<HashMap<_, Vec<_>>>();
+    let best_impact = |inst: &Instrument| {
+        impacts[inst]
+            .iter()
+            .copied()
+            .fold(Score::NEG_INFINITY, Score::max)
+    };
+
+    // The most valuable instruments first get the best spots. Or the largest
+    // groups, whose closeness bonus is the largest.
+    let mut by_value = counts.keys().copied().collect::<Vec<_>>();
+    by_value.sort_by_key(|inst| {
+        std::cmp::Reverse(OrderedFloat(best_impact(inst) * counts[inst] as Score))
+    });
+    let mut by_count = by_value.clone();
+    by_count.sort_by_key(|inst| std::cmp::Reverse(counts[inst]));
+
+    [by_value, by_count]
+        .iter()
+        .filter_map(|order| pack(problem, &slots, &centers, &impacts, order))
+        .map(|solution| {
+            let score = LocalState::new(problem, problem_id, spec, &solution).score;
+            info!("clusters: score: {score:.1}");
+            (OrderedFloat(score), solution)
+        })
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, solution)| solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_group_instruments() -> Result<()> {
+        let mut problem = Problem::example()?;
+        problem.musicians = vec![0, 1, 0, 1, 0, 1];
+        let solution = clusters(&problem, 0, Spec::V2).unwrap();
+        solution.validate(&problem)?;
+
+        // Everyone stands next to someone with the same instrument.
+        let placements = &solution.placements;
+        for i in 0..problem.musicians.len() {
+            assert!((0..problem.musicians.len()).any(|j| {
+                j != i
+                    && problem.musicians[i] == problem.musicians[j]
+                    && placements[i].distance(placements[j]) < 2.0 * MUSICIAN_RADIUS
+            }));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,28 @@
+pub mod batch;
+pub mod blocker;
 pub mod db;
 pub mod draw;
+pub mod edge_rows;
+pub mod elite;
+pub mod features;
 pub mod gui;
+pub mod initial;
+pub mod local_state;
+pub mod moves;
+pub mod observer;
+pub mod pillars;
+pub mod pipeline;
 pub mod prelude;
 pub mod problem;
+pub mod registry;
 pub mod solution;
 pub mod solver;
+pub mod solver_ga;
+pub mod solver_lahc;
+pub mod solver_lns;
+pub mod solver_polish;
 pub mod solver_sa;
+pub mod solver_tabu;
+pub mod store;
+pub mod transfer;
+pub mod tune;
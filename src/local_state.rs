@@ -0,0 +1,692 @@
+use crate::prelude::*;
+
+use crate::problem::*;
+use crate::solution::*;
+
+// https://gitlab.com/rafaelbocquet-cpcontests/icfpc23/-/blob/main/cxx/solve.cpp
+
+#[derive(Default)]
+struct AttNode {
+    angle: f64,
+    // Attendee's index.
+    index: usize,
+    nblock: u32,
+    x: Coord,
+    y: Coord,
+}
+
+pub struct LocalState<'a> {
+    pub problem_id: ProblemId,
+    pub problem: &'a Problem,
+    pub spec: Spec,
+    pub place: Vec<Point>,
+    // Musicians grouped by instrument.
+    pub classes: Vec<Vec<usize>>,
+    q: Vec<Score>,
+    angles: Vec<Vec<AttNode>>,
+    scores: Vec<Score>,
+    pub score: Score,
+    // Musicians taken off the stage, see `remove`.
+    removed: Vec<bool>,
+    // Primitive moves applied since the last commit, for rollback.
+    journal: Vec<Op>,
+}
+
+#[derive(Debug, Clone, Copy, derive_more::Display)]
+#[display("score: {score:.1}, audible: {audible}, muted: {muted}, blocker gain: {blocker_gain:.1}")]
+pub struct ScoreBreakdown {
+    pub score: Score,
+    pub audible: usize,
+    pub muted: usize,
+    // Score gained by placing muted musicians as blockers.
+    pub blocker_gain: Score,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Move { i: usize, from: Point },
+    Swap { a: usize, b: usize },
+    Remove { i: usize },
+    Insert { i: usize, from: Point },
+}
+
+// problem.hpp
+
+// Hexagonal lattice of positions on the stage, spaced just over the minimum
+// distance between musicians.
+pub fn candidate_slots(problem: &Problem) -> Vec<Point> {
+    let d = MUSICIAN_RADIUS + 1e-6;
+    let dy = d * 3.0f64.sqrt() / 2.0;
+    let minx = problem.stage_bottom_left[0] + MUSICIAN_RADIUS;
+    let maxx = problem.stage_bottom_left[0] + problem.stage_width - MUSICIAN_RADIUS;
+    let miny = problem.stage_bottom_left[1] + MUSICIAN_RADIUS;
+    let maxy = problem.stage_bottom_left[1] + problem.stage_height - MUSICIAN_RADIUS;
+
+    let mut slots = vec![];
+    let mut y = miny;
+    let mut row = 0;
+    while y <= maxy {
+        let mut x = if row % 2 == 0 { minx } else { minx + d / 2.0 };
+        while x <= maxx {
+            slots.push(Point::new(x, y));
+            x += d;
+        }
+        y += dy;
+        row += 1;
+    }
+    slots
+}
+
+// Positions along the four stage edges, spaced just over the minimum distance
+// between musicians. Corners come first and are not repeated.
+pub fn edge_slots(problem: &Problem) -> Vec<Point> {
+    let d = MUSICIAN_RADIUS + 1e-6;
+    let minx = problem.stage_bottom_left[0] + MUSICIAN_RADIUS;
+    let maxx = problem.stage_bottom_left[0] + problem.stage_width - MUSICIAN_RADIUS;
+    let miny = problem.stage_bottom_left[1] + MUSICIAN_RADIUS;
+    let maxy = problem.stage_bottom_left[1] + problem.stage_height - MUSICIAN_RADIUS;
+
+    let mut slots = vec![];
+    for (from, to) in [
+        (Point::new(minx, miny), Point::new(maxx, miny)),
+        (Point::new(maxx, miny), Point::new(maxx, maxy)),
+        (Point::new(maxx, maxy), Point::new(minx, maxy)),
+        (Point::new(minx, maxy), Point::new(minx, miny)),
+    ] {
+        let len = from.distance(to);
+        let n = (len / d).floor() as usize;
+        // The last one is the next edge's first.
+        for k in 0..n.max(1) {
+            let t = if len > 0.0 { k as f64 * d / len } else { 0.0 };
+            slots.push(Point::new(
+                from.x + t * (to.x - from.x),
+                from.y + t * (to.y - from.y),
+            ));
+        }
+    }
+    slots
+}
+
+fn norm_angle(mut angle: f64) -> f64 {
+    while angle < 0.0 {
+        angle += 2.0 * std::f64::consts::PI;
+    }
+    while angle > 2.0 * std::f64::consts::PI {
+        angle -= 2.0 * std::f64::consts::PI;
+    }
+    angle
+}
+
+impl<'a> LocalState<'a> {
+    pub fn new(
+        problem: &'a Problem,
+        problem_id: ProblemId,
+        spec: Spec,
+        solution: &Solution,
+    ) -> Self {
+        let nm = solution.placements.len();
+        let natt = problem.attendees.len();
+
+        let place = solution.placements.clone();
+
+        let angles = (0..nm)
+            .map(|_| {
+                (0..natt)
+                    .map(|j| AttNode {
+                        angle: 0.0,
+                        index: j,
+                        nblock: 0,
+                        x: problem.attendees[j].x,
+                        y: problem.attendees[j].y,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut classes: Vec<Vec<usize>> = vec![];
+        let mut class_of = HashMap::new();
+        for (i, inst) in problem.musicians.iter().enumerate() {
+            let c = *class_of.entry(*inst).or_insert_with(|| {
+                classes.push(vec![]);
+                classes.len() - 1
+            });
+            classes[c].push(i);
+        }
+
+        let mut state = LocalState {
+            problem_id,
+            problem,
+            spec,
+            place,
+            classes,
+            q: vec![1.0; nm],
+            angles,
+            scores: vec![0.0; nm],
+            score: 0.0,
+            removed: vec![false; nm],
+            journal: vec![],
+        };
+
+        state.cal_q();
+
+        for i in 0..nm {
+            state.make_angles(i);
+        }
+
+        state.update_score(Some(&solution.volumes));
+        // state.update_score(None);
+        state
+    }
+
+    fn update_score(&mut self, volumes: Option<&[f64]>) {
+        self.score = if let Some(volumes) = volumes {
+            (0..self.scores.len())
+                .map(|i| {
+                    // self.q[i] * self.scores[i] * volumes[i]
+                    let a = self.q[i] * self.scores[i];
+                    // // println!("m: {i}: score: {a}, volume: {}", volumes[i]);
+                    a * volumes[i]
+                })
+                .sum()
+        } else {
+            (0..self.scores.len())
+                .filter(|i| !self.removed[*i])
+                .map(|i| (self.q[i] * self.scores[i]).max(0.0) * 10.0)
+                .sum()
+        };
+    }
+
+    fn cal_q(&mut self) {
+        if self.is_full_round() {
+            for i in 0..self.q.len() {
+                for j in 0..self.q.len() {
+                    if i != j && self.problem.musicians[i] == self.problem.musicians[j] {
+                        self.q[i] += 1.0 / self.place[i].distance(self.place[j]);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn to_solution(&self) -> Solution {
+        let volumes = self
+            .scores
+            .iter()
+            .map(|score| if *score <= 1e-6 { 0.0 } else { 10.0 })
+            .collect();
+        Solution {
+            placements: self.place.clone(),
+            volumes,
+        }
+    }
+
+    fn is_full_round(&self) -> bool {
+        matches!(self.spec, Spec::V2)
+    }
+
+    fn make_angles(&mut self, i: usize) {
+        for j in 0..self.problem.attendees.len() {
+            let a = &mut self.angles[i][j];
+            let x = a.x - self.place[i].x;
+            let y = a.y - self.place[i].y;
+            a.angle = norm_angle(y.atan2(x));
+            a.nblock = 0;
+        }
+        // Sort attendeees by angle from musician i to attendee.
+        self.angles[i].sort_by_key(|a| OrderedFloat(a.angle));
+
+        self.scores[i] = 0.0;
+        for j in 0..self.problem.attendees.len() {
+            let d2 = self.place[i].distance_squared(self.problem.attendees[j].point());
+            self.scores[i] +=
+                1e6 * self.problem.attendees[j].tastes[self.problem.musicians[i]] / d2;
+        }
+
+        for j in 0..self.place.len() {
+            if i != j && !self.removed[j] {
+                self.add_blocks(i, j);
+            }
+        }
+
+        if self.is_full_round() {
+            self.add_pillars(i);
+        }
+    }
+
+    fn add_blocks(&mut self, i: usize, j: usize) {
+        let [r1, r2] = self.blocks_range(i, j);
+        for k in r1.into_iter().chain(r2) {
+            if self.angles[i][k].nblock == 0 {
+                let d2 = self.place[i]
+                    .distance_squared(self.problem.attendees[self.angles[i][k].index].point());
+                self.scores[i] -= 1e6
+                    * self.problem.attendees[self.angles[i][k].index].tastes
+                        [self.problem.musicians[i]]
+                    / d2;
+            }
+            self.angles[i][k].nblock += 1;
+        }
+    }
+
+    fn rem_blocks(&mut self, i: usize, j: usize) {
+        let [r1, r2] = self.blocks_range(i, j);
+        for k in r1.into_iter().chain(r2) {
+            self.angles[i][k].nblock -= 1;
+            if self.angles[i][k].nblock == 0 {
+                let d2 = self.place[i]
+                    .distance_squared(self.problem.attendees[self.angles[i][k].index].point());
+                self.scores[i] += 1e6
+                    * self.problem.attendees[self.angles[i][k].index].tastes
+                        [self.problem.musicians[i]]
+                    / d2;
+            }
+        }
+    }
+
+    fn add_pillars(&mut self, i: usize) {
+        for j in 0..self.problem.pillars.len() {
+            let dp2 = self.place[i].distance_squared(self.problem.pillars[j].center_point());
+            let [r1, r2] = self.pillars_range(i, j);
+            for k in r1.into_iter().chain(r2) {
+                let d2 = self.place[i]
+                    .distance_squared(self.problem.attendees[self.angles[i][k].index].point());
+                if d2 > dp2 {
+                    if self.angles[i][k].nblock == 0 {
+                        self.scores[i] -= 1e6
+                            * self.problem.attendees[self.angles[i][k].index].tastes
+                                [self.problem.musicians[i]]
+                            / d2;
+                    }
+                    self.angles[i][k].nblock += 1;
+                }
+            }
+        }
+    }
+
+    fn find_index(&self, angles: &[AttNode], angle: f64) -> usize {
+        let mut left = 0;
+        let mut right = angles.len();
+
+        while left < right {
+            let mid = left + (right - left) / 2;
+            if angles[mid].angle < angle {
+                left = mid + 1;
+            } else {
+                right = mid
+            }
+        }
+        left
+    }
+
+    fn blocks_range(&self, i: usize, j: usize) -> [Range<usize>; 2] {
+        let d = self.place[i].distance(self.place[j]);
+        let angle = (self.place[j].y - self.place[i].y).atan2(self.place[j].x - self.place[i].x);
+        let alpha = (BLOCK_RADIUS / d).asin();
+        let angle0 = norm_angle(angle - alpha);
+        let angle1 = norm_angle(angle + alpha);
+        let ix0 = self.find_index(&self.angles[i], angle0);
+        let ix1 = self.find_index(&self.angles[i], angle1);
+        if angle0 < angle1 {
+            assert!(ix0 <= ix1);
+            [(ix0..ix1), 0..0]
+        } else {
+            assert!(ix1 <= ix0);
+            [(ix0..self.problem.attendees.len()), (0..ix1)]
+        }
+    }
+
+    fn pillars_range(&self, i: usize, j: usize) -> [Range<usize>; 2] {
+        let center = self.problem.pillars[j].center_point();
+        let radius = self.problem.pillars[j].radius;
+
+        let d = self.place[i].distance(center);
+        let angle = (center.y - self.place[i].y).atan2(center.x - self.place[i].x);
+        let alpha = (radius / d).asin();
+        let angle0 = norm_angle(angle - alpha);
+        let angle1 = norm_angle(angle + alpha);
+        let ix0 = self.find_index(&self.angles[i], angle0);
+        let ix1 = self.find_index(&self.angles[i], angle1);
+        if angle0 < angle1 {
+            [(ix0..ix1), 0..0]
+        } else {
+            [(ix0..self.problem.attendees.len()), (0..ix1)]
+        }
+    }
+
+    fn do_move(&mut self, i: usize, to: Point) {
+        self.do_remove(i);
+        self.do_insert(i, to);
+        self.update_score(None);
+    }
+
+    // Musician i stops blocking the others and counting towards their q. Its
+    // own angles are left as they are and rebuilt when it comes back.
+    fn do_remove(&mut self, i: usize) {
+        let nm = self.place.len();
+
+        for j in 0..nm {
+            if i != j && !self.removed[j] {
+                self.rem_blocks(j, i);
+            }
+        }
+
+        if self.is_full_round() {
+            for j in 0..nm {
+                if i != j
+                    && !self.removed[j]
+                    && self.problem.musicians[i] == self.problem.musicians[j]
+                {
+                    self.q[j] -= 1.0 / self.place[i].distance(self.place[j]);
+                }
+            }
+        }
+
+        self.removed[i] = true;
+    }
+
+    fn do_insert(&mut self, i: usize, to: Point) {
+        let nm = self.place.len();
+
+        self.removed[i] = false;
+        self.place[i] = to;
+
+        self.q[i] = 1.0;
+        if self.is_full_round() {
+            for j in 0..nm {
+                if i != j
+                    && !self.removed[j]
+                    && self.problem.musicians[i] == self.problem.musicians[j]
+                {
+                    let qplus = 1.0 / self.place[i].distance(self.place[j]);
+                    self.q[i] += qplus;
+                    self.q[j] += qplus;
+                }
+            }
+        }
+
+        self.make_angles(i);
+
+        for j in 0..nm {
+            if i != j && !self.removed[j] {
+                self.add_blocks(j, i);
+            }
+        }
+    }
+
+    fn do_swap(&mut self, a: usize, b: usize) {
+        let nm = self.place.len();
+        debug_assert!(!self.removed[a] && !self.removed[b]);
+
+        if self.is_full_round() {
+            for i in [a, b] {
+                for j in 0..nm {
+                    if i != j && self.problem.musicians[i] == self.problem.musicians[j] {
+                        self.q[j] -= 1.0 / self.place[i].distance(self.place[j]);
+                    }
+                }
+            }
+        }
+
+        self.place.swap(a, b);
+        self.make_angles(a);
+        self.make_angles(b);
+
+        if self.is_full_round() {
+            self.q[a] = 1.0;
+            self.q[b] = 1.0;
+            for i in [a, b] {
+                for j in 0..nm {
+                    if i != j && self.problem.musicians[i] == self.problem.musicians[j] {
+                        let qplus = 1.0 / self.place[i].distance(self.place[j]);
+                        self.q[i] += qplus;
+                        self.q[j] += qplus;
+                    }
+                }
+            }
+            // Consider double count a <=> b
+            if self.problem.musicians[a] == self.problem.musicians[b] {
+                let qplus = 1.0 / self.place[a].distance(self.place[b]);
+                self.q[a] -= qplus;
+                self.q[b] -= qplus;
+            }
+        }
+        self.update_score(None);
+    }
+
+    // Musicians with non-positive impact, played at volume 0.
+    pub fn is_muted(&self, i: usize) -> bool {
+        self.scores[i] <= 1e-6
+    }
+
+    // Attendees who hate musician i's instrument and can still hear it, with
+    // the (positive) impact that blocking them would recover.
+    pub fn unblocked_haters(&self, i: usize) -> Vec<(usize, Score)> {
+        self.angles[i]
+            .iter()
+            .filter(|a| a.nblock == 0)
+            .filter_map(|a| {
+                let attendee = &self.problem.attendees[a.index];
+                let taste = attendee.tastes[self.problem.musicians[i]];
+                (taste < 0.0).then(|| {
+                    let d2 = self.place[i].distance_squared(attendee.point());
+                    (a.index, -1e6 * taste * self.q[i] / d2)
+                })
+            })
+            .collect()
+    }
+
+    pub fn breakdown(&self, blocker_gain: Score) -> ScoreBreakdown {
+        let muted = (0..self.place.len()).filter(|i| self.is_muted(*i)).count();
+        ScoreBreakdown {
+            score: self.score,
+            audible: self.place.len() - muted,
+            muted,
+            blocker_gain,
+        }
+    }
+
+    // Transactional API: primitive moves are journaled until `commit`, and
+    // `rollback` undoes all of them in reverse order.
+
+    pub fn move_to(&mut self, i: usize, to: Point) {
+        self.journal.push(Op::Move {
+            i,
+            from: self.place[i],
+        });
+        self.do_move(i, to);
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.journal.push(Op::Swap { a, b });
+        self.do_swap(a, b);
+    }
+
+    // Takes musician i off the stage until `insert`: it neither scores, nor
+    // blocks, nor collides. Its place is kept but means nothing meanwhile.
+    pub fn remove(&mut self, i: usize) {
+        assert!(!self.removed[i]);
+        self.journal.push(Op::Remove { i });
+        self.do_remove(i);
+        self.update_score(None);
+    }
+
+    // Puts a removed musician i back at `to`.
+    pub fn insert(&mut self, i: usize, to: Point) {
+        assert!(self.removed[i]);
+        self.journal.push(Op::Insert {
+            i,
+            from: self.place[i],
+        });
+        self.do_insert(i, to);
+        self.update_score(None);
+    }
+
+    pub fn commit(&mut self) {
+        self.journal.clear();
+    }
+
+    pub fn rollback(&mut self) {
+        while let Some(op) = self.journal.pop() {
+            match op {
+                Op::Move { i, from } => self.do_move(i, from),
+                Op::Swap { a, b } => self.do_swap(a, b),
+                Op::Remove { i } => {
+                    self.do_insert(i, self.place[i]);
+                    self.update_score(None);
+                }
+                Op::Insert { i, from } => {
+                    self.do_remove(i);
+                    self.place[i] = from;
+                    self.update_score(None);
+                }
+            }
+        }
+    }
+
+    // Whether musician i can't stand at p because of the stage or the others
+    // on it.
+    pub fn collides(&self, i: usize, p: Point) -> bool {
+        !self.problem.on_stage(p)
+            || (0..self.place.len()).any(|j| {
+                i != j
+                    && !self.removed[j]
+                    && p.distance_squared(self.place[j]) < MUSICIAN_RADIUS_2 + EPS
+            })
+    }
+
+    // Score change if musician i moved to p. The state is left unchanged.
+    // Both sides are scored at the best volumes, whatever `score` is based on.
+    pub fn delta_move(&mut self, i: usize, to: Point) -> Score {
+        let from = self.place[i];
+        let score = self.score;
+        self.do_move(i, to);
+        let moved = self.score;
+        self.do_move(i, from);
+        let delta = moved - self.score;
+        self.score = score;
+        delta
+    }
+
+    // Score change if the removed musician i were put back at p. The state is
+    // left unchanged.
+    pub fn delta_insert(&mut self, i: usize, to: Point) -> Score {
+        let from = self.place[i];
+        let score = self.score;
+        self.do_insert(i, to);
+        self.update_score(None);
+        let inserted = self.score;
+        self.do_remove(i);
+        self.place[i] = from;
+        self.update_score(None);
+        let delta = inserted - self.score;
+        self.score = score;
+        delta
+    }
+
+    // `new` scores the solution's own volumes, but moves score every musician
+    // at its best volume. Solvers comparing `score` before and after moves
+    // rebase it first.
+    pub fn rebase_score(&mut self) {
+        self.update_score(None);
+    }
+
+    pub fn is_placeable(&self, i: usize) -> bool {
+        !self.collides(i, self.place[i])
+    }
+
+    // Musicians within radius of p, nearest first.
+    pub fn neighbors(&self, p: Point, radius: Coord) -> Vec<usize> {
+        let mut res = (0..self.place.len())
+            .filter(|j| p.distance_squared(self.place[*j]) < radius * radius)
+            .collect::<Vec<_>>();
+        res.sort_by_key(|j| OrderedFloat(p.distance_squared(self.place[*j])));
+        res
+    }
+
+    #[allow(dead_code)]
+    fn assert_score(&self) {
+        let solution = self.to_solution();
+        let new_state = LocalState::new(self.problem, self.problem_id, self.spec, &solution);
+        assert_relative_eq!(self.score, new_state.score, max_relative = 1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidate_slots_test() -> Result<()> {
+        let problem = Problem::example()?;
+        let slots = candidate_slots(&problem);
+        assert!(!slots.is_empty());
+        assert!(slots.iter().all(|p| problem.on_stage(*p)));
+        for (i, p) in slots.iter().enumerate() {
+            for q in &slots[i + 1..] {
+                assert!(p.distance_squared(*q) > MUSICIAN_RADIUS_2 + EPS);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn delta_move_test() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let mut st = LocalState::new(&problem, 0, Spec::V2, &solution);
+        let score = st.score;
+        let to = Point::new(1200.0, 100.0);
+        let delta = st.delta_move(0, to);
+        assert_relative_eq!(st.score, score, epsilon = 1e-6);
+
+        // The example's volumes aren't the best ones, so the delta is relative
+        // to the score at the best volumes.
+        let base = LocalState::new(&problem, 0, Spec::V2, &st.to_solution()).score;
+        assert!(base > score);
+        st.move_to(0, to);
+        assert_relative_eq!(st.score, base + delta, epsilon = 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn remove_insert_test() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let to = Point::new(1200.0, 100.0);
+        let rebased = |problem: &Problem, spec, solution: &Solution| {
+            let mut st = LocalState::new(problem, 0, spec, solution);
+            st.rebase_score();
+            st.score
+        };
+        for spec in [Spec::V1, Spec::V2] {
+            for i in 0..solution.placements.len() {
+                let mut st = LocalState::new(&problem, 0, spec, &solution);
+                st.rebase_score();
+                let score = st.score;
+
+                // Removed is as if the musician weren't in the problem.
+                st.remove(i);
+                let mut without = problem.clone();
+                without.musicians.remove(i);
+                let mut rest = solution.clone();
+                rest.placements.remove(i);
+                rest.volumes.remove(i);
+                assert_relative_eq!(st.score, rebased(&without, spec, &rest), epsilon = 1e-6);
+                assert!(!st.collides(i, solution.placements[i]));
+
+                let removed = st.score;
+                let delta = st.delta_insert(i, to);
+                assert_relative_eq!(st.score, removed, epsilon = 1e-6);
+                st.insert(i, to);
+                let mut moved = solution.clone();
+                moved.placements[i] = to;
+                assert_relative_eq!(st.score, rebased(&problem, spec, &moved), epsilon = 1e-6);
+                assert_relative_eq!(st.score, removed + delta, epsilon = 1e-6);
+
+                st.rollback();
+                assert_relative_eq!(st.score, score, epsilon = 1e-6);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,21 @@
 use clap::Parser;
 
+use icfp2024::batch;
+use icfp2024::db;
 use icfp2024::draw;
+use icfp2024::elite;
+use icfp2024::features;
 use icfp2024::gui;
+use icfp2024::observer;
+use icfp2024::pipeline;
 use icfp2024::prelude::*;
 use icfp2024::problem::*;
+use icfp2024::registry;
 use icfp2024::solution;
 use icfp2024::solver;
-use icfp2024::solver_sa;
+use icfp2024::store;
+use icfp2024::transfer;
+use icfp2024::tune;
 
 #[derive(Parser, Debug)]
 #[clap(name = "icfp2024")]
@@ -20,13 +29,110 @@ enum Cli {
         solution_path: PathBuf,
         out_path: PathBuf,
     },
+    // The pillars' shadows over the problem, and the solution if given.
+    DrawShadows {
+        id: ProblemId,
+        out_path: PathBuf,
+        #[arg(long)]
+        solution_path: Option<PathBuf>,
+    },
     Solve {
         id: ProblemId,
         #[arg(long)]
         initial_solution_path: Option<PathBuf>,
         #[arg(long)]
         gui: bool,
+        // See `solvers`.
+        #[arg(long, default_value = "sa")]
+        solver: String,
+        // e.g. --param end=600s --param temp0=50
+        #[arg(long = "param", value_parser = registry::parse_param)]
+        params: Vec<(String, String)>,
+        // Solvers run in turn instead of --solver, e.g. "sa:end=60s,lns:k=4".
+        #[arg(long, conflicts_with_all = ["solver", "params", "resume"])]
+        pipeline: Option<String>,
+        // Continue from the last checkpoint. Same as --param resume=true.
+        #[arg(long)]
+        resume: bool,
+        // e.g. --observe log:5 --observe plot. Defaults to plot, wip and log.
+        #[arg(long = "observe")]
+        observers: Vec<observer::ObserverSpec>,
     },
+    // Solves many problems in parallel within a total wall-clock budget.
+    SolveAll {
+        // "all", or ids and ranges, e.g. "1-10,15".
+        #[arg(default_value = "all")]
+        ids: String,
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+        // Total wall-clock budget in seconds.
+        #[arg(long, default_value_t = 3_600)]
+        budget: u64,
+        #[arg(long, value_enum, default_value_t = batch::Weighting::Size)]
+        weight: batch::Weighting,
+        // The recommended solver for each problem instead of SA.
+        #[arg(long)]
+        recommend: bool,
+    },
+    // Problem features and the recommended solver and weight for each.
+    Recommend {
+        #[arg(default_value = "all")]
+        ids: String,
+    },
+    // Races SA configurations per size class and saves the winners, see
+    // tune.rs.
+    Tune {
+        #[arg(default_value = "all")]
+        ids: String,
+        // Problems per size class.
+        #[arg(long, default_value_t = 3)]
+        sample: usize,
+        // Seconds per run.
+        #[arg(long, default_value_t = 10)]
+        budget: u64,
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    // The elite pools, see elite.rs.
+    Elite {
+        #[arg(default_value = "all")]
+        ids: String,
+        // Offer the solutions in solution/all first.
+        #[arg(long)]
+        import: bool,
+    },
+    // The solve runs on a problem, oldest first, the best marked.
+    Runs {
+        id: ProblemId,
+    },
+    // Stores the files under solution/ in the database, see store.rs.
+    ImportSolutions {
+        #[arg(default_value = "all")]
+        ids: String,
+    },
+    // Writes solution/best from the database.
+    ExportBest {
+        #[arg(default_value = "all")]
+        ids: String,
+    },
+    // Writes solution/submission from the database.
+    ExportSubmission {
+        #[arg(default_value = "all")]
+        ids: String,
+    },
+    // Records the solution as submitted now.
+    MarkSubmitted {
+        id: ProblemId,
+        path: PathBuf,
+    },
+    // Maps the best solutions of problems with the same stage onto this one.
+    Transfer {
+        id: ProblemId,
+    },
+    // Lists the solvers and their params.
+    Solvers,
     // Bench {
     //     id: ProblemId,
     // },
@@ -50,47 +156,126 @@ fn main() -> Result<()> {
         } => {
             draw::draw_solution_file(id, solution_path, out_path)?;
         }
+        Cli::DrawShadows {
+            id,
+            out_path,
+            solution_path,
+        } => {
+            draw::draw_shadows(id, solution_path, out_path)?;
+        }
         Cli::Solve {
             id,
             initial_solution_path,
             gui,
+            solver,
+            mut params,
+            pipeline,
+            resume,
+            mut observers,
         } => {
-            println!("{id}, gui: {gui}");
+            let build: Box<dyn FnOnce(registry::Setup) -> Result<registry::BoxedSolver> + Send> =
+                match pipeline {
+                    Some(pipeline) => {
+                        println!("{id}, pipeline: {pipeline}, gui: {gui}");
+                        let stages = pipeline::parse_stages(&pipeline)?;
+                        Box::new(move |setup| {
+                            Ok(Box::new(pipeline::Pipeline::new(id, stages, setup)?))
+                        })
+                    }
+                    None => {
+                        println!("{id}, solver: {solver}, gui: {gui}");
+                        let entry = registry::find(&solver)?;
+                        if resume {
+                            params.push(("resume".to_string(), "true".to_string()));
+                        }
+                        // Tuned for the size class, unless given.
+                        let class = tune::SizeClass::of(&Problem::new(id)?);
+                        let mut args = tune::SolverConfig::load()?.params(&solver, class);
+                        args.extend(params);
+                        let params = entry.params(&args)?;
+                        Box::new(move |setup| entry.build(id, &params, setup))
+                    }
+                };
+            let initial_solution =
+                initial_solution_path.and_then(|path| solution::Solution::from(path).ok());
+            let cancel = solver::Cancel::on_signals()?;
+            if observers.is_empty() {
+                observers = observer::ObserverSpec::defaults();
+            }
+            if gui
+                && !observers
+                    .iter()
+                    .any(|o| o.kind == observer::ObserverKind::Gui)
+            {
+                observers.push(observer::ObserverSpec::new(observer::ObserverKind::Gui));
+            }
             if gui {
                 let (sender, receiver) = async_channel::bounded(1);
+                let setup = registry::Setup {
+                    initial_solution,
+                    observers: observer::build(&observers, Some(sender))?,
+                };
                 std::thread::spawn(move || {
                     solver::solve(
-                        solver_sa::SolverSa::new(
-                            id,
-                            // 5_000_000.0,
-                            Some(100.0),
-                            // 3 hours.
-                            // solver_sa::End::MaxDuration(std::time::Duration::from_secs(12 * 3_600)),
-                            solver_sa::End::MaxDuration(std::time::Duration::from_secs(60)),
-                            // solver_sa::End::MaxDuration(std::time::Duration::from_secs(3600)),
-                            initial_solution_path
-                                .and_then(|path| solution::Solution::from(path).ok()),
-                            Some(sender),
-                        )
-                        .expect("new?"),
+                        build(setup).expect("new?"),
+                        std::time::Duration::MAX,
+                        &cancel,
                     )
                     .expect("solve?");
                 });
                 gui::run(id, receiver);
             } else {
-                solver::solve(solver_sa::SolverSa::new(
-                    id,
-                    // 5_000_000.0,
-                    Some(100.0),
-                    // 3 hours.
-                    // solver_sa::End::MaxDuration(std::time::Duration::from_secs(12 * 3_600)),
-                    solver_sa::End::MaxDuration(std::time::Duration::from_secs(60)),
-                    // solver_sa::End::MaxDuration(std::time::Duration::from_secs(3600)),
-                    initial_solution_path.and_then(|path| solution::Solution::from(path).ok()),
-                    None,
-                )?)?;
+                let setup = registry::Setup {
+                    initial_solution,
+                    observers: observer::build(&observers, None)?,
+                };
+                solver::solve(build(setup)?, std::time::Duration::MAX, &cancel)?;
             };
         }
+        Cli::Solvers => registry::print_solvers(),
+        Cli::SolveAll {
+            ids,
+            workers,
+            budget,
+            weight,
+            recommend,
+        } => {
+            let cancel = solver::Cancel::on_signals()?;
+            batch::solve_all(
+                &batch::parse_ids(&ids)?,
+                workers,
+                std::time::Duration::from_secs(budget),
+                weight,
+                recommend,
+                &cancel,
+            )?;
+        }
+        Cli::Recommend { ids } => {
+            features::print(&batch::parse_ids(&ids)?)?;
+        }
+        Cli::ImportSolutions { ids } => {
+            store::import(&batch::parse_ids(&ids)?)?;
+        }
+        Cli::ExportBest { ids } => {
+            store::export_best(&batch::parse_ids(&ids)?)?;
+        }
+        Cli::ExportSubmission { ids } => {
+            store::export_submission(&batch::parse_ids(&ids)?)?;
+        }
+        Cli::MarkSubmitted { id, path } => {
+            store::mark_submitted(id, &path)?;
+        }
+        Cli::Runs { id } => {
+            db::print_runs(id)?;
+        }
+        Cli::Elite { ids, import } => {
+            for id in batch::parse_ids(&ids)? {
+                if import {
+                    elite::import(id)?;
+                }
+                elite::print(id)?;
+            }
+        }
         // Cli::Bench { id } => {
         //     solver::solve(solver_sa::SolverSa::new(
         //         id,
@@ -105,6 +290,27 @@ fn main() -> Result<()> {
         //     let score = solver_sa::score(&problem, id, id.into(), &solution);
         //     println!("{score}");
         // }
+        Cli::Tune {
+            ids,
+            sample,
+            budget,
+            workers,
+            seed,
+        } => {
+            let ids = batch::parse_ids(&ids)?;
+            let cancel = solver::Cancel::on_signals()?;
+            tune::tune(
+                &ids,
+                sample,
+                std::time::Duration::from_secs(budget),
+                workers,
+                seed,
+                &cancel,
+            )?;
+        }
+        Cli::Transfer { id } => {
+            transfer::run(id)?;
+        }
         Cli::BestScoreRefresh => {
             // solution::BestScore::refresh()?;
         }
@@ -0,0 +1,517 @@
+use crate::prelude::*;
+
+use crate::local_state::*;
+use crate::pillars::{self, ShadowMap};
+use rand::seq::SliceRandom;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum MoveKind {
+    #[display("swap")]
+    Swap,
+    // Swap all musicians of one instrument with those of another.
+    #[display("class-swap")]
+    ClassSwap,
+    #[display("teleport")]
+    Teleport,
+    #[display("slide")]
+    Slide,
+    #[display("jitter")]
+    Jitter,
+    #[display("translate")]
+    GroupTranslate,
+    #[display("rotate")]
+    ClusterRotate,
+    #[display("row-shift")]
+    RowShift,
+    #[display("push")]
+    Push,
+}
+
+// Where the move weights start before they adapt: as they were, favouring
+// small moves, or favouring moves across the stage.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum MoveMix {
+    #[display("default")]
+    Default,
+    #[display("local")]
+    Local,
+    #[display("global")]
+    Global,
+}
+
+impl std::str::FromStr for MoveMix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "default" => MoveMix::Default,
+            "local" => MoveMix::Local,
+            "global" => MoveMix::Global,
+            _ => bail!("unknown move mix: {s}"),
+        })
+    }
+}
+
+impl MoveKind {
+    pub const ALL: [MoveKind; 9] = [
+        MoveKind::Swap,
+        MoveKind::ClassSwap,
+        MoveKind::Teleport,
+        MoveKind::Slide,
+        MoveKind::Jitter,
+        MoveKind::GroupTranslate,
+        MoveKind::ClusterRotate,
+        MoveKind::RowShift,
+        MoveKind::Push,
+    ];
+
+    // The fixed mix used before moves were adapted online, plus a small share
+    // for the compound moves.
+    fn initial_weight(self, mix: MoveMix) -> f64 {
+        let weight = match self {
+            MoveKind::Swap => 0.1,
+            MoveKind::Teleport => 0.09,
+            MoveKind::Slide => 0.09,
+            MoveKind::Jitter => 0.72,
+            MoveKind::ClassSwap
+            | MoveKind::GroupTranslate
+            | MoveKind::ClusterRotate
+            | MoveKind::RowShift
+            | MoveKind::Push => 0.02,
+        };
+        match (mix, self) {
+            (MoveMix::Local, MoveKind::Jitter | MoveKind::Slide) => 2.0 * weight,
+            (MoveMix::Global, MoveKind::Swap | MoveKind::ClassSwap | MoveKind::Teleport) => {
+                3.0 * weight
+            }
+            _ => weight,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug)]
+pub struct MoveStats {
+    pub proposals: usize,
+    pub collisions: usize,
+    // Proposals which can't change anything, e.g. swaps on a single instrument.
+    pub wasted: usize,
+    pub accepts: usize,
+    pub improvements: usize,
+    pub delta_sum: Score,
+}
+
+impl MoveStats {
+    pub fn avg_delta(&self) -> Score {
+        let evaluated = self.proposals - self.collisions - self.wasted;
+        if evaluated == 0 {
+            0.0
+        } else {
+            self.delta_sum / evaluated as Score
+        }
+    }
+}
+
+// Adaptive pursuit style bandit: each move keeps a moving average of how often
+// it improves the current score, and moves are drawn proportionally to it,
+// with a floor so that no move starves.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MoveSelector {
+    rewards: Vec<f64>,
+    stats: Vec<MoveStats>,
+}
+
+impl MoveSelector {
+    const LEARNING_RATE: f64 = 0.01;
+    const MIN_PROBABILITY: f64 = 0.02;
+
+    pub fn new() -> Self {
+        Self::with_mix(MoveMix::Default)
+    }
+
+    pub fn with_mix(mix: MoveMix) -> Self {
+        MoveSelector {
+            rewards: MoveKind::ALL
+                .iter()
+                .map(|k| k.initial_weight(mix))
+                .collect(),
+            stats: vec![MoveStats::default(); MoveKind::ALL.len()],
+        }
+    }
+
+    pub fn probability(&self, kind: MoveKind) -> f64 {
+        let total = self.rewards.iter().sum::<f64>();
+        let n = MoveKind::ALL.len() as f64;
+        let share = if total > 0.0 {
+            self.rewards[kind as usize] / total
+        } else {
+            1.0 / n
+        };
+        Self::MIN_PROBABILITY + (1.0 - n * Self::MIN_PROBABILITY) * share
+    }
+
+    pub fn select(&mut self, rng: &mut StdRng) -> MoveKind {
+        let mut r = rng.random_range(0.0..1.0);
+        for kind in MoveKind::ALL {
+            let p = self.probability(kind);
+            if r < p {
+                self.stats[kind as usize].proposals += 1;
+                return kind;
+            }
+            r -= p;
+        }
+        let kind = MoveKind::Jitter;
+        self.stats[kind as usize].proposals += 1;
+        kind
+    }
+
+    pub fn collided(&mut self, kind: MoveKind) {
+        self.stats[kind as usize].collisions += 1;
+        self.reward(kind, 0.0);
+    }
+
+    pub fn wasted(&mut self, kind: MoveKind) {
+        self.stats[kind as usize].wasted += 1;
+        self.reward(kind, 0.0);
+    }
+
+    pub fn evaluated(&mut self, kind: MoveKind, delta: Score, accepted: bool) {
+        let stats = &mut self.stats[kind as usize];
+        stats.delta_sum += delta;
+        if accepted {
+            stats.accepts += 1;
+        }
+        if delta > 0.0 {
+            stats.improvements += 1;
+        }
+        self.reward(kind, if delta > 0.0 { 1.0 } else { 0.0 });
+    }
+
+    fn reward(&mut self, kind: MoveKind, reward: f64) {
+        let r = &mut self.rewards[kind as usize];
+        *r += Self::LEARNING_RATE * (reward - *r);
+    }
+
+    pub fn stats(&self, kind: MoveKind) -> &MoveStats {
+        &self.stats[kind as usize]
+    }
+
+    pub fn write_plot(&self, out: &mut impl Write, niter: usize) -> Result<()> {
+        for kind in MoveKind::ALL {
+            let s = self.stats(kind);
+            writeln!(
+                out,
+                "{} {} {} {} {} {} {} {:.1} {:.3}",
+                niter,
+                kind,
+                s.proposals,
+                s.collisions,
+                s.wasted,
+                s.accepts,
+                s.improvements,
+                s.avg_delta(),
+                self.probability(kind)
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn log_summary(&self) {
+        for kind in MoveKind::ALL {
+            let s = self.stats(kind);
+            info!(
+                "move: {kind}, proposals: {}, collisions: {}, wasted: {}, accepts: {}, improvements: {}, avg_delta: {:.1}, probability: {:.3}",
+                s.proposals,
+                s.collisions,
+                s.wasted,
+                s.accepts,
+                s.improvements,
+                s.avg_delta(),
+                self.probability(kind)
+            );
+        }
+    }
+}
+
+impl Default for MoveSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const JITTER_DISTANCE: Coord = 40.0;
+const CLUSTER_RADIUS: Coord = 30.0;
+const MAX_CLUSTER: usize = 8;
+
+fn random_direction(rng: &mut StdRng) -> (Coord, Coord) {
+    let angle = rng.random_range(0.0f64..2.0 * std::f64::consts::PI);
+    (angle.cos(), angle.sin())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Applied,
+    // The caller must roll back whatever was applied.
+    Collided,
+    // Nothing was applied.
+    Wasted,
+}
+
+impl From<bool> for Outcome {
+    fn from(placeable: bool) -> Self {
+        if placeable {
+            Outcome::Applied
+        } else {
+            Outcome::Collided
+        }
+    }
+}
+
+// Two distinct instrument classes, if any.
+pub fn two_classes(st: &LocalState, rng: &mut StdRng) -> Option<(usize, usize)> {
+    let nclass = st.classes.len();
+    if nclass < 2 {
+        return None;
+    }
+    let a = rng.random_range(0..nclass);
+    let b = (a + rng.random_range(1..nclass)) % nclass;
+    Some((a, b))
+}
+
+// Applies a random move of the given kind to the state through its
+// transactional API.
+// With a shadow map, teleports favour positions with clear sight lines.
+pub fn apply(
+    kind: MoveKind,
+    st: &mut LocalState,
+    shadows: Option<&ShadowMap>,
+    rng: &mut StdRng,
+) -> Outcome {
+    let nm = st.place.len();
+
+    match kind {
+        MoveKind::Swap => {
+            // Swapping musicians of the same instrument changes nothing.
+            let Some((ca, cb)) = two_classes(st, rng) else {
+                return Outcome::Wasted;
+            };
+            let a = st.classes[ca][rng.random_range(0..st.classes[ca].len())];
+            let b = st.classes[cb][rng.random_range(0..st.classes[cb].len())];
+            st.swap(a, b);
+            Outcome::Applied
+        }
+        MoveKind::ClassSwap => {
+            let Some((ca, cb)) = two_classes(st, rng) else {
+                return Outcome::Wasted;
+            };
+            // With different class sizes, the larger class keeps a random
+            // subset of its positions.
+            let mut a = st.classes[ca].clone();
+            let mut b = st.classes[cb].clone();
+            a.shuffle(rng);
+            b.shuffle(rng);
+            for (a, b) in a.into_iter().zip(b) {
+                st.swap(a, b);
+            }
+            Outcome::Applied
+        }
+        MoveKind::Teleport | MoveKind::Slide | MoveKind::Jitter => {
+            let id = rng.random_range(0..nm);
+            let p0 = st.place[id];
+            let p = match kind {
+                MoveKind::Teleport => match shadows {
+                    Some(map) => pillars::random_clear_point(st.problem, map, rng),
+                    None => st.problem.random_point_on_stage(rng),
+                },
+                MoveKind::Slide => {
+                    let dist = JITTER_DISTANCE * rng.random_range(0.0f64..1.0).powi(2);
+                    let (dx, dy) = random_direction(rng);
+
+                    let mut lo = 0.0;
+                    let mut hi = dist;
+                    while hi - lo > 1e-3 {
+                        let mi = (lo + hi) / 2.0;
+                        if st.collides(id, Point::new(p0.x + mi * dx, p0.y + mi * dy)) {
+                            hi = mi;
+                        } else {
+                            lo = mi;
+                        }
+                    }
+                    Point::new(p0.x + lo * dx, p0.y + lo * dy)
+                }
+                // TODO: Gradient
+                _ => {
+                    let dist = JITTER_DISTANCE * rng.random_range(0.0f64..1.0).powi(2);
+                    let (dx, dy) = random_direction(rng);
+                    Point::new(p0.x + dist * dx, p0.y + dist * dy)
+                }
+            };
+            if st.collides(id, p) {
+                return Outcome::Collided;
+            }
+            st.move_to(id, p);
+            Outcome::Applied
+        }
+        MoveKind::GroupTranslate => {
+            let group = cluster(st, rng);
+            let dist = 20.0 * rng.random_range(0.0f64..1.0).powi(2);
+            let (dx, dy) = random_direction(rng);
+            translate(st, group, dist * dx, dist * dy).into()
+        }
+        MoveKind::ClusterRotate => {
+            let group = cluster(st, rng);
+            if group.len() < 2 {
+                return Outcome::Wasted;
+            }
+            let n = group.len() as Coord;
+            let cx = group.iter().map(|i| st.place[*i].x).sum::<Coord>() / n;
+            let cy = group.iter().map(|i| st.place[*i].y).sum::<Coord>() / n;
+            let theta = rng.random_range(-0.5f64..0.5);
+            let (sin, cos) = theta.sin_cos();
+            for &i in &group {
+                let p = st.place[i];
+                let (x, y) = (p.x - cx, p.y - cy);
+                st.move_to(
+                    i,
+                    Point::new(cx + x * cos - y * sin, cy + x * sin + y * cos),
+                );
+            }
+            group.iter().all(|i| st.is_placeable(*i)).into()
+        }
+        MoveKind::RowShift => {
+            let problem = st.problem;
+            let minx = problem.stage_bottom_left[0] + MUSICIAN_RADIUS;
+            let maxx = problem.stage_bottom_left[0] + problem.stage_width - MUSICIAN_RADIUS;
+            let miny = problem.stage_bottom_left[1] + MUSICIAN_RADIUS;
+            let maxy = problem.stage_bottom_left[1] + problem.stage_height - MUSICIAN_RADIUS;
+
+            // Distance from the edge, and the direction along it.
+            let (edge, (dx, dy)): (Box<dyn Fn(Point) -> Coord>, _) = match rng.random_range(0..4) {
+                0 => (Box::new(move |p: Point| p.y - miny), (1.0, 0.0)),
+                1 => (Box::new(move |p: Point| maxy - p.y), (1.0, 0.0)),
+                2 => (Box::new(move |p: Point| p.x - minx), (0.0, 1.0)),
+                _ => (Box::new(move |p: Point| maxx - p.x), (0.0, 1.0)),
+            };
+            let row = (0..nm)
+                .filter(|i| edge(st.place[*i]) < MUSICIAN_RADIUS)
+                .collect::<Vec<_>>();
+            if row.is_empty() {
+                return Outcome::Wasted;
+            }
+            let dist = rng.random_range(-MUSICIAN_RADIUS..MUSICIAN_RADIUS);
+            translate(st, row, dist * dx, dist * dy).into()
+        }
+        MoveKind::Push => {
+            let id = rng.random_range(0..nm);
+            let p0 = st.place[id];
+            let dist = JITTER_DISTANCE * rng.random_range(0.0f64..1.0).powi(2);
+            let (dx, dy) = random_direction(rng);
+            let p = Point::new(p0.x + dist * dx, p0.y + dist * dy);
+            if !st.problem.on_stage(p) {
+                return Outcome::Collided;
+            }
+            // Instead of stopping at the first collision like Slide, move the
+            // neighbours out of the way, radially from the new position.
+            let pushed = st
+                .neighbors(p, MUSICIAN_RADIUS + EPS)
+                .into_iter()
+                .filter(|j| *j != id)
+                .collect::<Vec<_>>();
+            for &j in &pushed {
+                let q = st.place[j];
+                let d = p.distance(q);
+                let (ux, uy) = if d < EPS {
+                    (dx, dy)
+                } else {
+                    ((q.x - p.x) / d, (q.y - p.y) / d)
+                };
+                let r = MUSICIAN_RADIUS + 1e-6;
+                st.move_to(j, Point::new(p.x + r * ux, p.y + r * uy));
+            }
+            st.move_to(id, p);
+            (st.is_placeable(id) && pushed.iter().all(|j| st.is_placeable(*j))).into()
+        }
+    }
+}
+
+// A random musician and its nearest neighbours.
+fn cluster(st: &LocalState, rng: &mut StdRng) -> Vec<usize> {
+    let pivot = rng.random_range(0..st.place.len());
+    let mut group = st.neighbors(st.place[pivot], CLUSTER_RADIUS);
+    group.truncate(MAX_CLUSTER);
+    group
+}
+
+fn translate(st: &mut LocalState, mut group: Vec<usize>, dx: Coord, dy: Coord) -> bool {
+    // Move the leading musicians first so that the group doesn't run over
+    // itself on the way.
+    group.sort_by_key(|i| OrderedFloat(-(st.place[*i].x * dx + st.place[*i].y * dy)));
+    for &i in &group {
+        let p = st.place[i];
+        st.move_to(i, Point::new(p.x + dx, p.y + dy));
+    }
+    group.iter().all(|i| st.is_placeable(*i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problem::*;
+    use crate::solution::*;
+
+    #[test]
+    fn move_selector_adapts() {
+        let mut moves = MoveSelector::new();
+        let total = MoveKind::ALL
+            .iter()
+            .map(|k| moves.probability(*k))
+            .sum::<f64>();
+        assert_relative_eq!(total, 1.0, epsilon = 1e-9);
+
+        let p0 = moves.probability(MoveKind::Teleport);
+        for _ in 0..100 {
+            moves.evaluated(MoveKind::Teleport, 1.0, true);
+            moves.collided(MoveKind::Jitter);
+        }
+        assert!(moves.probability(MoveKind::Teleport) > p0);
+        assert_eq!(moves.stats(MoveKind::Teleport).improvements, 100);
+        assert_eq!(moves.stats(MoveKind::Jitter).collisions, 100);
+    }
+
+    #[test]
+    fn swap_across_instruments() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let mut st = LocalState::new(&problem, 0, Spec::V1, &solution);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Musicians are [0, 1, 0], so every swap must involve musician 1.
+        let p1 = st.place[1];
+        for _ in 0..20 {
+            assert_eq!(apply(MoveKind::Swap, &mut st, None, &mut rng), Outcome::Applied);
+            assert!(st.place[1].distance(p1) > 1.0);
+            st.rollback();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn apply_and_rollback() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let mut st = LocalState::new(&problem, 0, Spec::V2, &solution);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let place0 = st.place.clone();
+        for _ in 0..100 {
+            for kind in MoveKind::ALL {
+                apply(kind, &mut st, None, &mut rng);
+                st.rollback();
+            }
+        }
+        for (p, q) in st.place.iter().zip(place0.iter()) {
+            assert_relative_eq!(p.x, q.x, epsilon = 1e-6);
+            assert_relative_eq!(p.y, q.y, epsilon = 1e-6);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,391 @@
+use crate::prelude::*;
+
+use crate::draw;
+use crate::local_state::*;
+use crate::moves::*;
+use crate::problem::*;
+use crate::solution::*;
+use std::time::{Duration, Instant};
+
+// Where a running solver reports to: the GUI, plot files, WIP drawings and the
+// log. Each observer is throttled to its own rate.
+
+pub struct Progress<'a, 'p> {
+    pub name: &'a str,
+    pub problem_id: ProblemId,
+    // Non-zero when the run was resumed from a checkpoint.
+    pub start_iteration: usize,
+    pub iteration: usize,
+    pub elapsed: Duration,
+    pub score: Score,
+    pub best: Score,
+    pub temperature: f64,
+    pub accept_rate: f64,
+    pub accept_rate_positive: f64,
+    pub accept_rate_negative: f64,
+    pub ncollide: usize,
+    pub nmove: usize,
+    // None for solvers without the SA moves.
+    pub moves: Option<&'a MoveSelector>,
+    pub state: &'a LocalState<'p>,
+}
+
+pub trait ProgressObserver {
+    fn progress(&mut self, progress: &Progress) -> Result<()>;
+
+    fn new_best(
+        &mut self,
+        _problem_id: ProblemId,
+        _score: Score,
+        _solution: &Solution,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    // Called once with the final state.
+    fn finish(&mut self, progress: &Progress) -> Result<()> {
+        self.progress(progress)
+    }
+}
+
+pub struct Throttled<O> {
+    inner: O,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl<O> Throttled<O> {
+    pub fn new(inner: O, interval: Duration) -> Self {
+        Throttled {
+            inner,
+            interval,
+            last: None,
+        }
+    }
+}
+
+impl<O: ProgressObserver> ProgressObserver for Throttled<O> {
+    fn progress(&mut self, progress: &Progress) -> Result<()> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        self.last = Some(Instant::now());
+        self.inner.progress(progress)
+    }
+
+    fn new_best(&mut self, problem_id: ProblemId, score: Score, solution: &Solution) -> Result<()> {
+        self.inner.new_best(problem_id, score, solution)
+    }
+
+    fn finish(&mut self, progress: &Progress) -> Result<()> {
+        self.inner.finish(progress)
+    }
+}
+
+impl<O: ProgressObserver + ?Sized> ProgressObserver for Box<O> {
+    fn progress(&mut self, progress: &Progress) -> Result<()> {
+        (**self).progress(progress)
+    }
+
+    fn new_best(&mut self, problem_id: ProblemId, score: Score, solution: &Solution) -> Result<()> {
+        (**self).new_best(problem_id, score, solution)
+    }
+
+    fn finish(&mut self, progress: &Progress) -> Result<()> {
+        (**self).finish(progress)
+    }
+}
+
+#[derive(Default)]
+pub struct Observers(Vec<Box<dyn ProgressObserver + Send>>);
+
+impl Observers {
+    pub fn push(&mut self, observer: impl ProgressObserver + Send + 'static) {
+        self.0.push(Box::new(observer));
+    }
+}
+
+impl ProgressObserver for Observers {
+    fn progress(&mut self, progress: &Progress) -> Result<()> {
+        self.0.iter_mut().try_for_each(|o| o.progress(progress))
+    }
+
+    fn new_best(&mut self, problem_id: ProblemId, score: Score, solution: &Solution) -> Result<()> {
+        self.0
+            .iter_mut()
+            .try_for_each(|o| o.new_best(problem_id, score, solution))
+    }
+
+    fn finish(&mut self, progress: &Progress) -> Result<()> {
+        self.0.iter_mut().try_for_each(|o| o.finish(progress))
+    }
+}
+
+// One set of observers for several solvers run in turn.
+pub struct Shared<O>(std::sync::Arc<std::sync::Mutex<O>>);
+
+impl<O> Shared<O> {
+    pub fn new(inner: O) -> Self {
+        Shared(std::sync::Arc::new(std::sync::Mutex::new(inner)))
+    }
+
+    pub fn lock(&self) -> std::sync::MutexGuard<'_, O> {
+        self.0.lock().unwrap()
+    }
+}
+
+impl<O> Clone for Shared<O> {
+    fn clone(&self) -> Self {
+        Shared(self.0.clone())
+    }
+}
+
+impl<O: ProgressObserver> ProgressObserver for Shared<O> {
+    fn progress(&mut self, progress: &Progress) -> Result<()> {
+        self.0.lock().unwrap().progress(progress)
+    }
+
+    fn new_best(&mut self, problem_id: ProblemId, score: Score, solution: &Solution) -> Result<()> {
+        self.0.lock().unwrap().new_best(problem_id, score, solution)
+    }
+
+    fn finish(&mut self, progress: &Progress) -> Result<()> {
+        self.0.lock().unwrap().finish(progress)
+    }
+}
+
+pub struct GuiObserver(async_channel::Sender<Solution>);
+
+impl ProgressObserver for GuiObserver {
+    fn progress(&mut self, progress: &Progress) -> Result<()> {
+        Ok(self.0.send_blocking(progress.state.to_solution())?)
+    }
+}
+
+// plot/sa/{solver}/{id}.data and {id}-moves.data, for gnuplot.
+#[derive(Default)]
+pub struct PlotObserver {
+    files: Option<(std::fs::File, std::fs::File)>,
+}
+
+impl PlotObserver {
+    fn open(progress: &Progress) -> Result<(std::fs::File, std::fs::File)> {
+        // Plots are appended to when resuming.
+        let resumed = progress.start_iteration > 0;
+        let open = |path: String, header: &str| -> Result<std::fs::File> {
+            let path = project_path(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            let mut f = std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(resumed)
+                .truncate(!resumed)
+                .open(path)?;
+            if !resumed {
+                writeln!(f, "{header}")?;
+            }
+            Ok(f)
+        };
+        Ok((
+            open(
+                format!("plot/sa/{}/{}.data", progress.name, progress.problem_id),
+                "iteration score best temperature acceptrate acceptrate_positive acceptrate_negative",
+            )?,
+            open(
+                format!("plot/sa/{}/{}-moves.data", progress.name, progress.problem_id),
+                "iteration move proposals collisions wasted accepts improvements avgdelta probability",
+            )?,
+        ))
+    }
+}
+
+impl ProgressObserver for PlotObserver {
+    fn progress(&mut self, progress: &Progress) -> Result<()> {
+        if self.files.is_none() {
+            self.files = Some(Self::open(progress)?);
+        }
+        let (sa_plot, moves_plot) = self.files.as_mut().unwrap();
+        writeln!(
+            sa_plot,
+            "{} {:.1} {:.1} {:.1} {:.3} {:.3} {:.3}",
+            progress.iteration,
+            progress.score,
+            progress.best,
+            progress.temperature,
+            progress.accept_rate,
+            progress.accept_rate_positive,
+            progress.accept_rate_negative
+        )?;
+        match progress.moves {
+            Some(moves) => moves.write_plot(moves_plot, progress.iteration),
+            None => Ok(()),
+        }
+    }
+}
+
+// draw/wip/{id}.svg
+pub struct WipObserver;
+
+impl ProgressObserver for WipObserver {
+    fn progress(&mut self, progress: &Progress) -> Result<()> {
+        draw::draw_svg(
+            progress.state.problem,
+            Some(&progress.state.to_solution()),
+            project_path(format!("draw/wip/{}.svg", progress.problem_id)),
+        )
+    }
+}
+
+pub struct LogObserver;
+
+impl ProgressObserver for LogObserver {
+    fn progress(&mut self, p: &Progress) -> Result<()> {
+        info!(
+            "temp: {:.1}, niter: {}, sc: {:.1}, best: {:.1}, ncollide: {}, nmove: {}, accept_rate: {:.02}, accept_rate_positive: {:.02}, accept_rate_negative: {:.02}",
+            p.temperature,
+            p.iteration,
+            p.score,
+            p.best,
+            p.ncollide,
+            p.nmove,
+            p.accept_rate,
+            p.accept_rate_positive,
+            p.accept_rate_negative
+        );
+        Ok(())
+    }
+
+    fn new_best(
+        &mut self,
+        problem_id: ProblemId,
+        score: Score,
+        _solution: &Solution,
+    ) -> Result<()> {
+        debug!("new best: problem_id: {problem_id}, score: {score:.1}");
+        Ok(())
+    }
+
+    fn finish(&mut self, progress: &Progress) -> Result<()> {
+        self.progress(progress)?;
+        if let Some(moves) = progress.moves {
+            moves.log_summary();
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum ObserverKind {
+    #[display("gui")]
+    Gui,
+    #[display("plot")]
+    Plot,
+    #[display("wip")]
+    Wip,
+    #[display("log")]
+    Log,
+}
+
+impl ObserverKind {
+    fn default_interval(self) -> Duration {
+        match self {
+            ObserverKind::Gui => Duration::from_millis(100),
+            ObserverKind::Plot => Duration::from_secs(1),
+            ObserverKind::Wip => Duration::from_secs(60),
+            ObserverKind::Log => Duration::from_secs(10),
+        }
+    }
+}
+
+// `kind` or `kind:seconds`, e.g. `plot:0.5`.
+#[derive(Copy, Clone, Debug)]
+pub struct ObserverSpec {
+    pub kind: ObserverKind,
+    pub interval: Duration,
+}
+
+impl ObserverSpec {
+    pub fn new(kind: ObserverKind) -> Self {
+        ObserverSpec {
+            kind,
+            interval: kind.default_interval(),
+        }
+    }
+
+    // What `solve` reports to unless told otherwise.
+    pub fn defaults() -> Vec<ObserverSpec> {
+        [ObserverKind::Plot, ObserverKind::Wip, ObserverKind::Log]
+            .into_iter()
+            .map(ObserverSpec::new)
+            .collect()
+    }
+}
+
+impl std::str::FromStr for ObserverSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, interval) = match s.split_once(':') {
+            Some((kind, secs)) => (kind, Some(secs.parse::<f64>()?)),
+            None => (s, None),
+        };
+        let kind = match kind {
+            "gui" => ObserverKind::Gui,
+            "plot" => ObserverKind::Plot,
+            "wip" => ObserverKind::Wip,
+            "log" => ObserverKind::Log,
+            _ => bail!("unknown observer: {kind}"),
+        };
+        Ok(match interval {
+            Some(secs) => ObserverSpec {
+                kind,
+                interval: Duration::try_from_secs_f64(secs)
+                    .with_context(|| format!("invalid interval: {secs}"))?,
+            },
+            None => ObserverSpec::new(kind),
+        })
+    }
+}
+
+pub fn build(
+    specs: &[ObserverSpec],
+    sender: Option<async_channel::Sender<Solution>>,
+) -> Result<Observers> {
+    let mut observers = Observers::default();
+    for spec in specs {
+        match spec.kind {
+            ObserverKind::Gui => {
+                let sender = sender.clone().context("gui observer without gui")?;
+                observers.push(Throttled::new(GuiObserver(sender), spec.interval));
+            }
+            ObserverKind::Plot => {
+                observers.push(Throttled::new(PlotObserver::default(), spec.interval))
+            }
+            ObserverKind::Wip => observers.push(Throttled::new(WipObserver, spec.interval)),
+            ObserverKind::Log => observers.push(Throttled::new(LogObserver, spec.interval)),
+        }
+    }
+    Ok(observers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_observer_spec() -> Result<()> {
+        let spec: ObserverSpec = "plot".parse()?;
+        assert_eq!(spec.kind, ObserverKind::Plot);
+        assert_eq!(spec.interval, Duration::from_secs(1));
+
+        let spec: ObserverSpec = "log:0.5".parse()?;
+        assert_eq!(spec.kind, ObserverKind::Log);
+        assert_eq!(spec.interval, Duration::from_millis(500));
+
+        assert!("svg".parse::<ObserverSpec>().is_err());
+        for bad in ["log:-1", "log:nan", "log:1e30", "log:x"] {
+            assert!(bad.parse::<ObserverSpec>().is_err(), "{bad}");
+        }
+        Ok(())
+    }
+}
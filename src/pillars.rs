@@ -0,0 +1,262 @@
+use crate::prelude::*;
+use std::f64::consts::PI;
+
+use crate::problem::*;
+
+// Pillars (Spec::V2 only) hide attendees from a musician when the sight line
+// between them passes through a pillar. Seen from an attendee, a pillar casts
+// a shadow: the wedge between its two tangents, beyond the pillar. The
+// heuristics use how much of the audience a position can still reach.
+
+// Directions from a point are bucketed into this many bins.
+const BINS: usize = 360;
+// The shadow map has at most this many cells along a side.
+const MAX_CELLS: usize = 64;
+// Points tried by `random_clear_point` before it takes whatever it has.
+const MAX_TRIES: usize = 8;
+
+fn bin(angle: f64) -> usize {
+    (((angle + PI) / (2.0 * PI) * BINS as f64).floor() as usize).min(BINS - 1)
+}
+
+// The pillars around a point, bucketed by direction, so that checking a sight
+// line only looks at the few pillars in its direction.
+pub struct Sight<'a> {
+    p: Point,
+    pillars: &'a [Pillar],
+    bins: Vec<Vec<usize>>,
+}
+
+impl<'a> Sight<'a> {
+    pub fn new(problem: &'a Problem, p: Point) -> Sight<'a> {
+        let mut bins = vec![vec![]; BINS];
+        for (j, pillar) in problem.pillars.iter().enumerate() {
+            let c = pillar.center_point();
+            let d = p.distance(c);
+            if d <= pillar.radius {
+                // Standing inside a pillar: it blocks every direction.
+                bins.iter_mut().for_each(|b| b.push(j));
+                continue;
+            }
+            let angle = (c.y - p.y).atan2(c.x - p.x);
+            let half = (pillar.radius / d).asin();
+            // One extra bin on each side for rounding.
+            let to_bin = |t: f64| ((t + PI) / (2.0 * PI) * BINS as f64).floor() as i64;
+            let lo = to_bin(angle - half) - 1;
+            let hi = to_bin(angle + half) + 1;
+            for k in lo..=hi.min(lo + BINS as i64 - 1) {
+                bins[k.rem_euclid(BINS as i64) as usize].push(j);
+            }
+        }
+        Sight {
+            p,
+            pillars: &problem.pillars,
+            bins,
+        }
+    }
+
+    pub fn is_blocked(&self, a: Point) -> bool {
+        let angle = (a.y - self.p.y).atan2(a.x - self.p.x);
+        self.bins[bin(angle)].iter().any(|j| {
+            let pillar = &self.pillars[*j];
+            is_line_circle_intersect(self.p, a, pillar.center_point(), pillar.radius)
+        })
+    }
+}
+
+pub fn is_shadowed(problem: &Problem, p: Point, a: Point) -> bool {
+    problem
+        .pillars
+        .iter()
+        .any(|pillar| is_line_circle_intersect(p, a, pillar.center_point(), pillar.radius))
+}
+
+// The attendees a musician at p can't reach because of pillars.
+pub fn shadowed_attendees(problem: &Problem, p: Point) -> Vec<usize> {
+    if problem.pillars.is_empty() {
+        return vec![];
+    }
+    let sight = Sight::new(problem, p);
+    (0..problem.attendees.len())
+        .filter(|k| sight.is_blocked(problem.attendees[*k].point()))
+        .collect()
+}
+
+// How much each instrument played at p pleases the audience, counting pillars
+// but not other musicians.
+pub fn impacts(problem: &Problem, p: Point) -> Vec<Score> {
+    let ninst = problem.attendees.first().map_or(0, |a| a.tastes.len());
+    let mut impacts = vec![0.0; ninst];
+    let sight = Sight::new(problem, p);
+    for a in &problem.attendees {
+        if sight.is_blocked(a.point()) {
+            continue;
+        }
+        let d2 = a.point().distance_squared(p);
+        for (impact, taste) in impacts.iter_mut().zip(&a.tastes) {
+            *impact += taste / d2;
+        }
+    }
+    impacts
+}
+
+// The shadow a pillar casts as seen from an attendee at a: the tangent points
+// on the pillar and the tangents' points at `reach` from a. None if a is
+// inside the pillar.
+pub fn shadow(a: Point, pillar: &Pillar, reach: Coord) -> Option<[Point; 4]> {
+    let c = pillar.center_point();
+    let d = a.distance(c);
+    if d <= pillar.radius {
+        return None;
+    }
+    let angle = (c.y - a.y).atan2(c.x - a.x);
+    let half = (pillar.radius / d).asin();
+    let tangent = (d * d - pillar.radius * pillar.radius).sqrt();
+    let at = |t: f64, r: Coord| Point::new(a.x + r * t.cos(), a.y + r * t.sin());
+    let reach = reach.max(tangent);
+    Some([
+        at(angle - half, tangent),
+        at(angle - half, reach),
+        at(angle + half, reach),
+        at(angle + half, tangent),
+    ])
+}
+
+// For a grid over the stage, the share of the audience a musician there still
+// reaches past the pillars. Each attendee counts its best taste over the
+// squared distance, if positive.
+#[derive(Debug, Clone)]
+pub struct ShadowMap {
+    origin: Point,
+    cell: Coord,
+    nx: usize,
+    ny: usize,
+    clear: Vec<f64>,
+}
+
+impl ShadowMap {
+    pub fn new(problem: &Problem) -> ShadowMap {
+        let [x0, y0] = problem.stage_bottom_left;
+        let origin = Point::new(x0, y0);
+        if problem.pillars.is_empty() {
+            return ShadowMap {
+                origin,
+                cell: 1.0,
+                nx: 0,
+                ny: 0,
+                clear: vec![],
+            };
+        }
+        let cell = (problem.stage_width.max(problem.stage_height) / MAX_CELLS as Coord)
+            .max(MUSICIAN_RADIUS);
+        let nx = (problem.stage_width / cell).ceil().max(1.0) as usize;
+        let ny = (problem.stage_height / cell).ceil().max(1.0) as usize;
+        let mut clear = vec![1.0; nx * ny];
+        for gy in 0..ny {
+            for gx in 0..nx {
+                let p = Point::new(
+                    (x0 + (gx as Coord + 0.5) * cell).min(x0 + problem.stage_width),
+                    (y0 + (gy as Coord + 0.5) * cell).min(y0 + problem.stage_height),
+                );
+                let sight = Sight::new(problem, p);
+                let (mut total, mut seen) = (0.0, 0.0);
+                for a in &problem.attendees {
+                    let w = a.taste_max().max(0.0) / a.point().distance_squared(p);
+                    total += w;
+                    if !sight.is_blocked(a.point()) {
+                        seen += w;
+                    }
+                }
+                if total > 0.0 {
+                    clear[gy * nx + gx] = seen / total;
+                }
+            }
+        }
+        ShadowMap {
+            origin,
+            cell,
+            nx,
+            ny,
+            clear,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clear.is_empty()
+    }
+
+    // The cells as (bottom-left, side, clear fraction).
+    pub fn cells(&self) -> impl Iterator<Item = (Point, Coord, f64)> + '_ {
+        (0..self.ny).flat_map(move |gy| {
+            (0..self.nx).map(move |gx| {
+                let p = Point::new(
+                    self.origin.x + gx as Coord * self.cell,
+                    self.origin.y + gy as Coord * self.cell,
+                );
+                (p, self.cell, self.clear[gy * self.nx + gx])
+            })
+        })
+    }
+
+    pub fn clear(&self, p: Point) -> f64 {
+        if self.is_empty() {
+            return 1.0;
+        }
+        let gx = ((p.x - self.origin.x) / self.cell).floor().max(0.0) as usize;
+        let gy = ((p.y - self.origin.y) / self.cell).floor().max(0.0) as usize;
+        self.clear[gy.min(self.ny - 1) * self.nx + gx.min(self.nx - 1)]
+    }
+}
+
+// A random point on the stage, accepted with the probability of its sight
+// lines being clear. Without pillars, just a random point.
+pub fn random_clear_point(problem: &Problem, map: &ShadowMap, rng: &mut StdRng) -> Point {
+    let p = problem.random_point_on_stage(rng);
+    if map.is_empty() {
+        return p;
+    }
+    let mut p = p;
+    for _ in 1..MAX_TRIES {
+        if rng.random_bool(map.clear(p).clamp(0.0, 1.0)) {
+            break;
+        }
+        p = problem.random_point_on_stage(rng);
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadows_match_sight_lines() -> Result<()> {
+        let mut problem = Problem::example()?;
+        problem.pillars = vec![Pillar {
+            center: [1100.0, 350.0],
+            radius: 50.0,
+        }];
+
+        let p = Point::new(1100.0, 200.0);
+        for (k, a) in problem.attendees.iter().enumerate() {
+            assert_eq!(
+                shadowed_attendees(&problem, p).contains(&k),
+                is_shadowed(&problem, p, a.point())
+            );
+        }
+
+        // A point straight behind the pillar is in the shadow of an attendee
+        // in front of it.
+        let a = Point::new(1100.0, 600.0);
+        let [t1, _, _, t2] = shadow(a, &problem.pillars[0], 1000.0).unwrap();
+        let c = problem.pillars[0].center_point();
+        assert_relative_eq!(t1.distance(c), 50.0, max_relative = 1e-9);
+        assert_relative_eq!(t2.distance(c), 50.0, max_relative = 1e-9);
+        assert!(is_shadowed(&problem, Point::new(1100.0, 100.0), a));
+
+        let map = ShadowMap::new(&problem);
+        assert!(!map.is_empty());
+        assert!((0.0..=1.0).contains(&map.clear(p)));
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use crate::prelude::*;
+
+use crate::observer::{Observers, ProgressObserver, Shared};
+use crate::problem::*;
+use crate::registry::{self, Entry, Params, Setup};
+use crate::solution::*;
+use crate::solver::*;
+use std::time::{Duration, Instant};
+
+// Registry solvers run in turn, each starting from the best solution so far.
+//
+// Stages are separated by ',', and each stage is a solver name followed by
+// ':name=value' params, e.g. "sa:end=60s:temp0=50,lns:k=4".
+
+pub struct Stage {
+    entry: &'static Entry,
+    params: Params,
+}
+
+pub fn parse_stages(s: &str) -> Result<Vec<Stage>> {
+    let stages = s
+        .split(',')
+        .map(|stage| {
+            let mut parts = stage.split(':');
+            let entry = registry::find(parts.next().unwrap())?;
+            let args = parts
+                .map(registry::parse_param)
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Stage {
+                entry,
+                params: entry.params(&args)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!stages.is_empty(), "empty pipeline");
+    Ok(stages)
+}
+
+#[derive(Debug, Clone)]
+pub struct StageReport {
+    pub solver_name: String,
+    pub score: Score,
+    pub elapsed: Duration,
+}
+
+pub struct Pipeline {
+    problem_id: ProblemId,
+    stages: Vec<Stage>,
+    initial_solution: Option<Solution>,
+    observers: Shared<Observers>,
+    reports: Vec<StageReport>,
+}
+
+impl Pipeline {
+    pub fn new(problem_id: ProblemId, stages: Vec<Stage>, setup: Setup) -> Result<Self> {
+        ensure!(!stages.is_empty(), "empty pipeline");
+        Ok(Self {
+            problem_id,
+            stages,
+            initial_solution: setup.initial_solution,
+            observers: Shared::new(setup.observers),
+            reports: vec![],
+        })
+    }
+
+    pub fn reports(&self) -> &[StageReport] {
+        &self.reports
+    }
+}
+
+impl Solver for Pipeline {
+    fn problem_id(&self) -> ProblemId {
+        self.problem_id
+    }
+
+    fn name(&self) -> String {
+        let names = self.stages.iter().map(|s| s.entry.name).collect::<Vec<_>>();
+        format!("pipeline-{}", names.join("-"))
+    }
+
+    // The stages as they would be given.
+    fn params(&self) -> String {
+        let stages = self
+            .stages
+            .iter()
+            .map(|s| format!("{}:{}", s.entry.name, s.params))
+            .collect::<Vec<_>>();
+        stages.join(",")
+    }
+
+    fn seed(&self) -> u64 {
+        0
+    }
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        self.initial_solution.as_ref()
+    }
+
+    // Shared with every stage.
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        self.observers.lock().push(observer);
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+        let mut best: Option<Solved> = None;
+        self.reports.clear();
+
+        for stage in &self.stages {
+            if cancel.is_cancelled() {
+                break;
+            }
+            let mut observers = Observers::default();
+            observers.push(self.observers.clone());
+            let setup = Setup {
+                initial_solution: match &best {
+                    Some(solved) => Some(Solution {
+                        placements: solved.placements.clone(),
+                        volumes: solved.volumes.clone(),
+                    }),
+                    None => self.initial_solution.clone(),
+                },
+                observers,
+            };
+            let mut solver = stage.entry.build(self.problem_id, &stage.params, setup)?;
+            let timer = Instant::now();
+            let solved = solver.solve(cancel)?;
+            let report = StageReport {
+                solver_name: solver.name(),
+                score: solved.score,
+                elapsed: timer.elapsed(),
+            };
+            info!(
+                "pipeline: {}, score: {:.1}, elapsed: {:?}",
+                report.solver_name, report.score, report.elapsed
+            );
+            self.reports.push(report);
+            // A stage that makes things worse is not passed on.
+            if best.as_ref().is_none_or(|b| solved.score > b.score) {
+                best = Some(solved);
+            }
+        }
+
+        for r in &self.reports {
+            println!(
+                "{:>40} {:>16.0} {:>7.1}s",
+                r.solver_name,
+                r.score,
+                r.elapsed.as_secs_f64()
+            );
+        }
+        let mut solved = best.context("pipeline cancelled before the first stage")?;
+        solved.solver_name = self.name();
+        Ok(solved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pipeline() -> Result<()> {
+        let stages = parse_stages("sa:end=60s:temp0=50,lns:k=4")?;
+        assert_eq!(stages.len(), 2);
+        assert_eq!(stages[0].entry.name, "sa");
+        assert_eq!(stages[0].params.get::<f64>("temp0")?, 50.0);
+        assert_eq!(stages[1].params.get::<usize>("k")?, 4);
+
+        assert!(parse_stages("sa:k=4").is_err());
+        assert!(parse_stages("sa,basic").is_err());
+        Ok(())
+    }
+}
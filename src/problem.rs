@@ -138,7 +138,7 @@ impl Problem {
         )
     }
 
-    fn distance_to_stage_squared(&self, p: Point) -> Coord {
+    pub fn distance_to_stage_squared(&self, p: Point) -> Coord {
         let minx = self.stage_bottom_left[0];
         let maxx = self.stage_bottom_left[0] + self.stage_width;
 
@@ -0,0 +1,499 @@
+use crate::prelude::*;
+
+use crate::initial::{self, InitialStrategy};
+use crate::observer::{Observers, ProgressObserver};
+use crate::pillars::ShadowMap;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver::*;
+use crate::solver_ga::SolverGa;
+use crate::solver_lahc::SolverLahc;
+use crate::solver_lns::{Acceptance, Repair, SolverLns};
+use crate::solver_polish::SolverPolish;
+use crate::solver_sa::{End, SolverSa};
+use crate::solver_tabu::SolverTabu;
+
+// Solvers by name, for `solve --solver <name> --param <name>=<value>`.
+
+#[derive(Copy, Clone, Debug)]
+pub enum ParamKind {
+    Int,
+    Float,
+    Bool,
+    // A duration such as "500ms", "60s", "5m" or "2h", or a number of iterations.
+    End,
+    Choice(&'static [&'static str]),
+}
+
+impl ParamKind {
+    fn check(&self, value: &str) -> Result<()> {
+        match self {
+            ParamKind::Int => {
+                value.parse::<usize>()?;
+            }
+            ParamKind::Float => {
+                value.parse::<f64>()?;
+            }
+            ParamKind::Bool => {
+                value.parse::<bool>()?;
+            }
+            ParamKind::End => {
+                value.parse::<End>()?;
+            }
+            ParamKind::Choice(choices) => {
+                ensure!(choices.contains(&value), "expected one of {choices:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ParamKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamKind::Int => write!(f, "int"),
+            ParamKind::Float => write!(f, "float"),
+            ParamKind::Bool => write!(f, "bool"),
+            ParamKind::End => write!(f, "end"),
+            ParamKind::Choice(choices) => write!(f, "{}", choices.join("|")),
+        }
+    }
+}
+
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub default: &'static str,
+    pub help: &'static str,
+}
+
+// Checked against the solver's specs, with defaults filled in.
+#[derive(Debug, Clone)]
+pub struct Params(HashMap<&'static str, String>);
+
+impl Params {
+    pub fn get<T: std::str::FromStr>(&self, name: &str) -> Result<T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let value = self.0.get(name).context(format!("no param {name}"))?;
+        value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("param {name}: {e}"))
+    }
+}
+
+// As in a pipeline stage, e.g. "end=60s:temp0=50".
+impl std::fmt::Display for Params {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut params = self.0.iter().collect::<Vec<_>>();
+        params.sort();
+        let params = params
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>();
+        write!(f, "{}", params.join(":"))
+    }
+}
+
+// What every solver can be handed, besides its params.
+#[derive(Default)]
+pub struct Setup {
+    pub initial_solution: Option<Solution>,
+    pub observers: Observers,
+}
+
+pub type BoxedSolver = Box<dyn Solver + Send>;
+
+type Build = fn(ProblemId, &Params, Setup) -> Result<BoxedSolver>;
+
+pub struct Entry {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub params: &'static [ParamSpec],
+    build: Build,
+}
+
+impl Entry {
+    // `args` are (name, value) pairs from the command line.
+    pub fn params(&self, args: &[(String, String)]) -> Result<Params> {
+        let mut params = self
+            .params
+            .iter()
+            .map(|p| (p.name, p.default.to_string()))
+            .collect::<HashMap<_, _>>();
+        for (name, value) in args {
+            let spec = self
+                .params
+                .iter()
+                .find(|p| p.name == name)
+                .with_context(|| format!("{} has no param {name}", self.name))?;
+            spec.kind
+                .check(value)
+                .with_context(|| format!("param {name}: invalid value {value}"))?;
+            params.insert(spec.name, value.clone());
+        }
+        Ok(Params(params))
+    }
+
+    pub fn build(
+        &self,
+        problem_id: ProblemId,
+        params: &Params,
+        setup: Setup,
+    ) -> Result<BoxedSolver> {
+        let solver = (self.build)(problem_id, params, setup)?;
+        Ok(Box::new(Built {
+            solver,
+            params: params.to_string(),
+        }))
+    }
+}
+
+// A solver with the params it was built with, for the runs table.
+struct Built {
+    solver: BoxedSolver,
+    params: String,
+}
+
+impl Solver for Built {
+    fn problem_id(&self) -> ProblemId {
+        self.solver.problem_id()
+    }
+
+    fn name(&self) -> String {
+        self.solver.name()
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+        self.solver.solve(cancel)
+    }
+
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        self.solver.observe(observer)
+    }
+
+    fn params(&self) -> String {
+        self.params.clone()
+    }
+
+    fn seed(&self) -> u64 {
+        self.solver.seed()
+    }
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        self.solver.initial_solution()
+    }
+}
+
+const END: ParamSpec = ParamSpec {
+    name: "end",
+    kind: ParamKind::End,
+    default: "60s",
+    help: "time limit, e.g. 60s, or a number of iterations",
+};
+
+const TEMP0: ParamSpec = ParamSpec {
+    name: "temp0",
+    kind: ParamKind::Float,
+    default: "100",
+    help: "initial temperature, 0 to derive it from the initial score",
+};
+
+fn temp0(params: &Params) -> Result<Option<f64>> {
+    let temp0 = params.get::<f64>("temp0")?;
+    Ok((temp0 > 0.0).then_some(temp0))
+}
+
+fn build_sa(problem_id: ProblemId, params: &Params, setup: Setup) -> Result<BoxedSolver> {
+    let problem = Problem::new(problem_id)?;
+    let shadows = params
+        .get::<bool>("shadows")?
+        .then(|| ShadowMap::new(&problem));
+    // A given initial solution wins over the strategy.
+    let initial_solution = match (setup.initial_solution, params.get("initial")?) {
+        (Some(solution), _) => Some(solution),
+        (None, InitialStrategy::Random) => shadows
+            .as_ref()
+            .map(|map| SolverSa::initial_solution_with(&problem, Some(map))),
+        (None, strategy) => Some(initial::initial_solution(
+            strategy,
+            &problem,
+            problem_id,
+            problem_id.into(),
+            SolverSa::SEED,
+        )),
+    };
+    let solver = SolverSa::new(
+        problem_id,
+        temp0(params)?,
+        params.get("end")?,
+        initial_solution,
+        setup.observers,
+    )?
+    .with_mix(params.get("mix")?)
+    .with_shadows(shadows);
+    Ok(Box::new(if params.get("resume")? {
+        solver.resume()?
+    } else {
+        solver
+    }))
+}
+
+fn build_lns(problem_id: ProblemId, params: &Params, setup: Setup) -> Result<BoxedSolver> {
+    let repair = match params.get::<String>("repair")?.as_str() {
+        "greedy" => Repair::Greedy,
+        _ => Repair::LocalSa,
+    };
+    let acceptance = match params.get::<String>("acceptance")?.as_str() {
+        "anneal" => Acceptance::Annealing {
+            temp0: params.get("temp0")?,
+        },
+        _ => Acceptance::LateAcceptance {
+            length: params.get("length")?,
+        },
+    };
+    Ok(Box::new(SolverLns::new(
+        problem_id,
+        params.get("k")?,
+        repair,
+        acceptance,
+        params.get("end")?,
+        setup.initial_solution,
+        setup.observers,
+    )?))
+}
+
+fn build_polish(problem_id: ProblemId, params: &Params, setup: Setup) -> Result<BoxedSolver> {
+    Ok(Box::new(SolverPolish::new(
+        problem_id,
+        params.get("step")?,
+        params.get("min_step")?,
+        setup.initial_solution,
+        setup.observers,
+    )?))
+}
+
+fn build_tabu(problem_id: ProblemId, params: &Params, setup: Setup) -> Result<BoxedSolver> {
+    Ok(Box::new(SolverTabu::new(
+        problem_id,
+        params.get("tenure")?,
+        params.get("neighborhood")?,
+        params.get("end")?,
+        setup.initial_solution,
+        setup.observers,
+    )?))
+}
+
+fn build_ga(problem_id: ProblemId, params: &Params, setup: Setup) -> Result<BoxedSolver> {
+    Ok(Box::new(SolverGa::new(
+        problem_id,
+        params.get("k")?,
+        params.get("anneal")?,
+        params.get("end")?,
+        setup.initial_solution,
+        setup.observers,
+    )?))
+}
+
+fn build_lahc(problem_id: ProblemId, params: &Params, setup: Setup) -> Result<BoxedSolver> {
+    Ok(Box::new(SolverLahc::new(
+        problem_id,
+        params.get("length")?,
+        params.get("end")?,
+        setup.initial_solution,
+        setup.observers,
+    )?))
+}
+
+pub static SOLVERS: &[Entry] = &[
+    Entry {
+        name: "sa",
+        description: "simulated annealing, then blocker placement",
+        params: &[
+            TEMP0,
+            END,
+            ParamSpec {
+                name: "initial",
+                kind: ParamKind::Choice(&["random", "clusters", "edges", "transfer", "elite"]),
+                default: "random",
+                help: "starting point without --initial-solution-path (edges: a DP heuristic)",
+            },
+            ParamSpec {
+                name: "mix",
+                kind: ParamKind::Choice(&["default", "local", "global"]),
+                default: "default",
+                help: "starting move weights",
+            },
+            ParamSpec {
+                name: "shadows",
+                kind: ParamKind::Bool,
+                default: "false",
+                help: "bias random placements toward clear sight lines past the pillars",
+            },
+            ParamSpec {
+                name: "resume",
+                kind: ParamKind::Bool,
+                default: "false",
+                help: "continue from the last checkpoint",
+            },
+        ],
+        build: build_sa,
+    },
+    Entry {
+        name: "lns",
+        description: "large neighbourhood search: destroy and repair",
+        params: &[
+            ParamSpec {
+                name: "k",
+                kind: ParamKind::Int,
+                default: "8",
+                help: "musicians removed per iteration",
+            },
+            ParamSpec {
+                name: "repair",
+                kind: ParamKind::Choice(&["greedy", "local-sa"]),
+                default: "greedy",
+                help: "how removed musicians are reinserted",
+            },
+            ParamSpec {
+                name: "acceptance",
+                kind: ParamKind::Choice(&["anneal", "late"]),
+                default: "late",
+                help: "when a repaired solution replaces the current one",
+            },
+            ParamSpec {
+                name: "temp0",
+                kind: ParamKind::Float,
+                default: "100",
+                help: "initial temperature for anneal",
+            },
+            ParamSpec {
+                name: "length",
+                kind: ParamKind::Int,
+                default: "1000",
+                help: "history length for late",
+            },
+            END,
+        ],
+        build: build_lns,
+    },
+    Entry {
+        name: "polish",
+        description: "coordinate descent to a local optimum, from the best by default",
+        params: &[
+            ParamSpec {
+                name: "step",
+                kind: ParamKind::Float,
+                default: "16",
+                help: "initial step",
+            },
+            ParamSpec {
+                name: "min_step",
+                kind: ParamKind::Float,
+                default: "0.001",
+                help: "stops once the step is below this",
+            },
+        ],
+        build: build_polish,
+    },
+    Entry {
+        name: "tabu",
+        description: "tabu search over lattice and edge slots",
+        params: &[
+            ParamSpec {
+                name: "tenure",
+                kind: ParamKind::Int,
+                default: "30",
+                help: "iterations a musician may not return to a slot it left",
+            },
+            ParamSpec {
+                name: "neighborhood",
+                kind: ParamKind::Int,
+                default: "32",
+                help: "moves sampled per iteration",
+            },
+            END,
+        ],
+        build: build_tabu,
+    },
+    Entry {
+        name: "ga",
+        description: "recombines the best solutions in solution/all",
+        params: &[
+            ParamSpec {
+                name: "k",
+                kind: ParamKind::Int,
+                default: "8",
+                help: "population size",
+            },
+            ParamSpec {
+                name: "anneal",
+                kind: ParamKind::Int,
+                default: "2000",
+                help: "annealing iterations per child",
+            },
+            END,
+        ],
+        build: build_ga,
+    },
+    Entry {
+        name: "lahc",
+        description: "late-acceptance hill climbing with the SA moves",
+        params: &[
+            ParamSpec {
+                name: "length",
+                kind: ParamKind::Int,
+                default: "5000",
+                help: "iterations back the score is compared with",
+            },
+            END,
+        ],
+        build: build_lahc,
+    },
+];
+
+pub fn find(name: &str) -> Result<&'static Entry> {
+    SOLVERS.iter().find(|e| e.name == name).with_context(|| {
+        let names = SOLVERS.iter().map(|e| e.name).collect::<Vec<_>>();
+        format!("unknown solver: {name}, expected one of {names:?}")
+    })
+}
+
+// `name=value`, for clap.
+pub fn parse_param(s: &str) -> Result<(String, String)> {
+    let (name, value) = s.split_once('=').context("expected name=value")?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+pub fn print_solvers() {
+    for entry in SOLVERS {
+        println!("{}: {}", entry.name, entry.description);
+        for p in entry.params {
+            println!(
+                "  {}: {} (default: {}) {}",
+                p.name, p.kind, p.default, p.help
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_params() -> Result<()> {
+        let lns = find("lns")?;
+        let params = lns.params(&[("k".to_string(), "3".to_string())])?;
+        assert_eq!(params.get::<usize>("k")?, 3);
+        assert_eq!(params.get::<String>("repair")?, "greedy");
+
+        assert!(lns.params(&[("k".to_string(), "x".to_string())]).is_err());
+        assert!(lns
+            .params(&[("resume".to_string(), "true".to_string())])
+            .is_err());
+        assert!(find("basic").is_err());
+        Ok(())
+    }
+}
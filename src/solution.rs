@@ -32,6 +32,70 @@ impl Solution {
         let solution: Solution = serde_json::from_str(&s).unwrap();
         Ok(solution)
     }
+
+    // Solver names and scores of the runs in solution/all, best first.
+    pub fn stored(id: ProblemId) -> Result<Vec<(String, Score, PathBuf)>> {
+        let dir = project_path("solution/all");
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut stored = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap().to_string_lossy();
+            if let Some((solver, score)) = stored_score(id, &name) {
+                stored.push((solver, score, path));
+            }
+        }
+        stored.sort_by_key(|(_, score, _)| std::cmp::Reverse(OrderedFloat(*score)));
+        Ok(stored)
+    }
+
+    // The k best in solution/all, best first.
+    pub fn top(id: ProblemId, k: usize) -> Result<Vec<(Score, Solution)>> {
+        Self::stored(id)?
+            .into_iter()
+            .take(k)
+            .map(|(_, score, path)| Ok((score, Solution::from(path)?)))
+            .collect()
+    }
+
+    pub fn validate(&self, problem: &Problem) -> Result<()> {
+        let nm = problem.musicians.len();
+        ensure!(
+            self.placements.len() == nm && self.volumes.len() == nm,
+            "expected {nm} musicians, got {} placements and {} volumes",
+            self.placements.len(),
+            self.volumes.len()
+        );
+        for (i, p) in self.placements.iter().enumerate() {
+            ensure!(problem.on_stage(*p), "musician {i} at {p} is off the stage");
+            for (j, q) in self.placements.iter().enumerate().skip(i + 1) {
+                ensure!(
+                    p.distance_squared(*q) >= MUSICIAN_RADIUS_2,
+                    "musicians {i} at {p} and {j} at {q} are too close"
+                );
+            }
+        }
+        for (i, v) in self.volumes.iter().enumerate() {
+            ensure!((0.0..=10.0).contains(v), "musician {i} has volume {v}");
+        }
+        Ok(())
+    }
+}
+
+// The solver and score in a solution/all file name, {id}-{solver}-{score}.json,
+// if the file is for the problem.
+fn stored_score(id: ProblemId, name: &str) -> Option<(String, Score)> {
+    let rest = name
+        .strip_prefix(&format!("{id}-"))?
+        .strip_suffix(".json")?;
+    let (solver, score) = rest.rsplit_once('-')?;
+    let score = score.parse::<Score>().ok()?;
+    Some(match solver.strip_suffix('-') {
+        Some(solver) => (solver.to_string(), -score),
+        None => (solver.to_string(), score),
+    })
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -158,6 +222,29 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn validate_example() -> Result<()> {
+        let problem = Problem::example()?;
+        let mut solution = Solution::example()?;
+        solution.validate(&problem)?;
+        solution.placements[2] = Point::new(1100.0, 105.0);
+        assert!(solution.validate(&problem).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn stored_score_test() {
+        assert_eq!(
+            stored_score(42, "42-sa-temp0-100-iter-10-123.5.json"),
+            Some(("sa-temp0-100-iter-10".to_string(), 123.5))
+        );
+        assert_eq!(
+            stored_score(42, "42-polish-16-0.001--7.json"),
+            Some(("polish-16-0.001".to_string(), -7.0))
+        );
+        assert_eq!(stored_score(4, "42-polish-16-0.001-7.json"), None);
+    }
+
     #[test]
     #[ignore]
     fn read_userboard() -> Result<()> {
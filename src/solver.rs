@@ -2,34 +2,127 @@ use crate::draw;
 use crate::prelude::*;
 
 use crate::db::*;
+use crate::elite;
+use crate::local_state::{LocalState, ScoreBreakdown};
+use crate::observer::{Progress, ProgressObserver, Shared};
 use crate::problem::*;
 use crate::solution::*;
+use crate::solver_sa;
+use crate::store;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
 
 pub trait Solver {
     fn problem_id(&self) -> ProblemId;
     fn name(&self) -> String;
-    fn solve(&mut self) -> Result<Solved>;
+    // Solvers should check `cancel` regularly, and return their best so far
+    // once it is cancelled.
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved>;
+
+    // Solvers that report progress pass their new bests on to this too. For
+    // the others, the end result is the only improvement.
+    fn observe(&mut self, _observer: Box<dyn ProgressObserver + Send>) {}
+
+    // For the runs table: the params as name=value pairs, the rng seed the run
+    // actually used, and where the solver starts.
+    fn params(&self) -> String {
+        String::new()
+    }
+
+    fn seed(&self) -> u64;
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        None
+    }
+}
+
+// For solvers picked at run time from the registry.
+impl<S: Solver + ?Sized> Solver for Box<S> {
+    fn problem_id(&self) -> ProblemId {
+        (**self).problem_id()
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+        (**self).solve(cancel)
+    }
+
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        (**self).observe(observer)
+    }
+
+    fn params(&self) -> String {
+        (**self).params()
+    }
+
+    fn seed(&self) -> u64 {
+        (**self).seed()
+    }
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        (**self).initial_solution()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Cancelled by SIGINT or SIGTERM. A second signal exits immediately.
+    pub fn on_signals() -> Result<Self> {
+        let cancel = Self::new();
+        let c = cancel.clone();
+        ctrlc::set_handler(move || {
+            if c.is_cancelled() {
+                std::process::exit(130);
+            }
+            eprintln!("Interrupted. Stopping at the next check...");
+            c.cancel();
+        })?;
+        Ok(cancel)
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
 }
 
+#[derive(Clone)]
 pub struct Solved {
     pub problem_id: ProblemId,
     pub solver_name: String,
     pub score: Score,
     pub placements: Vec<Point>,
     pub volumes: Vec<Score>,
+    pub breakdown: Option<ScoreBreakdown>,
 }
 
 impl Solved {
-    fn save_solution(&self) -> Result<()> {
-        self.save_solution_to(&format!(
+    // The path in solution/all.
+    fn save_solution(&self) -> Result<String> {
+        let path = format!(
             "solution/all/{}-{}-{}.json",
             self.problem_id, self.solver_name, self.score
-        ))?;
+        );
+        self.save_solution_to(&path)?;
 
         self.save_solution_to(&format!(
             "solution/{}/{}.json",
             self.solver_name, self.problem_id
-        ))
+        ))?;
+        Ok(path)
     }
 
     fn save_solution_to(&self, name: &str) -> Result<()> {
@@ -63,13 +156,26 @@ impl Solved {
             }
         };
         if is_best {
+            let problem = Problem::new(self.problem_id)?;
+            store::put(
+                &problem,
+                self.problem_id,
+                self.score,
+                &self.solution(),
+                &self.solver_name,
+            )?;
             self.save_solution_to(&format!("solution/best/{}.json", self.problem_id))?;
             update_score(self.problem_id, self.score)?;
         }
         Ok(())
     }
 
-    fn solution(&self) -> Solution {
+    pub fn validate(&self) -> Result<()> {
+        let problem = Problem::new(self.problem_id)?;
+        self.solution().validate(&problem)
+    }
+
+    pub fn solution(&self) -> Solution {
         Solution {
             placements: self.placements.clone(),
             volumes: self.volumes.clone(),
@@ -85,18 +191,324 @@ impl Solved {
     }
 }
 
-pub fn solve<T: Solver>(mut solver: T) -> Result<()> {
-    println!("Solving... {}", solver.problem_id());
-    let solved = solver.solve()?;
-    println!("Solved {}. score: {}", solver.problem_id(), solved.score);
-    solved.save_solution()?;
+// A new best found while solving, and when.
+pub struct Improvement {
+    pub elapsed: Duration,
+    pub solved: Solved,
+}
+
+// Passes a solver's new bests on to `solve_anytime`.
+struct ImprovementSender {
+    solver_name: String,
+    start: Instant,
+    sender: mpsc::Sender<Improvement>,
+}
+
+impl ProgressObserver for ImprovementSender {
+    fn progress(&mut self, _progress: &crate::observer::Progress) -> Result<()> {
+        Ok(())
+    }
+
+    fn new_best(&mut self, problem_id: ProblemId, score: Score, solution: &Solution) -> Result<()> {
+        let solved = Solved {
+            problem_id,
+            solver_name: self.solver_name.clone(),
+            score,
+            placements: solution.placements.clone(),
+            volumes: solution.volumes.clone(),
+            breakdown: None,
+        };
+        // Nobody listening any more is fine.
+        let _ = self.sender.send(Improvement {
+            elapsed: self.start.elapsed(),
+            solved,
+        });
+        Ok(())
+    }
+}
+
+// How often `solve_anytime` checks the deadline.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Runs the solver until it ends, is cancelled, or the deadline passes, and
+// calls `on_improvement` with new bests, strictly improving, at most once every
+// `interval`. A new best that comes sooner waits, and is passed on once the
+// interval is up unless a better one came. The best at the end is passed on
+// last, whatever the interval, if it wasn't already.
+pub fn solve_anytime<T: Solver + Send>(
+    solver: &mut T,
+    deadline: Duration,
+    interval: Duration,
+    cancel: &Cancel,
+    mut on_improvement: impl FnMut(&Improvement) -> Result<()>,
+) -> Result<Solved> {
+    let start = Instant::now();
+    let (sender, receiver) = mpsc::channel();
+    solver.observe(Box::new(ImprovementSender {
+        solver_name: solver.name(),
+        start,
+        sender,
+    }));
+
+    // Stopped by the caller or by the deadline.
+    let stop = Cancel::new();
+    let mut best: Option<Score> = None;
+    let mut pending: Option<Improvement> = None;
+    let mut last_report = Instant::now();
+    let mut report = |improvement: Option<Improvement>, end: bool| -> Result<()> {
+        if let Some(improvement) =
+            improvement.filter(|i| best.is_none_or(|best| i.solved.score > best))
+        {
+            best = Some(improvement.solved.score);
+            pending = Some(improvement);
+        }
+        if !end && last_report.elapsed() < interval {
+            return Ok(());
+        }
+        if let Some(improvement) = pending.take() {
+            last_report = Instant::now();
+            on_improvement(&improvement)?;
+        }
+        Ok(())
+    };
+
+    let solved = std::thread::scope(|s| -> Result<Solved> {
+        let handle = s.spawn(|| solver.solve(&stop));
+        while !handle.is_finished() {
+            if cancel.is_cancelled() || start.elapsed() >= deadline {
+                stop.cancel();
+            }
+            if let Err(e) = report(receiver.recv_timeout(POLL_INTERVAL).ok(), false) {
+                // The scope waits for the solver, which needn't run to its end.
+                stop.cancel();
+                return Err(e);
+            }
+        }
+        handle.join().unwrap()
+    })?;
+    for improvement in receiver.try_iter() {
+        report(Some(improvement), false)?;
+    }
+    report(
+        Some(Improvement {
+            elapsed: start.elapsed(),
+            solved: solved.clone(),
+        }),
+        true,
+    )?;
+    Ok(solved)
+}
+
+// The iterations a solver reports, summed over the stages of a pipeline.
+#[derive(Default)]
+struct IterationCounter {
+    done: usize,
+    last: usize,
+    reported: bool,
+}
+
+impl IterationCounter {
+    fn iterations(&self) -> Option<usize> {
+        self.reported.then_some(self.done + self.last)
+    }
+}
+
+impl ProgressObserver for IterationCounter {
+    fn progress(&mut self, progress: &Progress) -> Result<()> {
+        // A resumed run counts from where it resumed.
+        let iteration = progress.iteration - progress.start_iteration;
+        if iteration < self.last {
+            // The next stage.
+            self.done += self.last;
+        }
+        self.last = iteration;
+        self.reported = true;
+        Ok(())
+    }
+}
+
+// New bests are kept at most this often while solving.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+// Stops the solver once the deadline passes, see `solve_anytime`.
+pub fn solve<T: Solver + Send>(
+    mut solver: T,
+    deadline: Duration,
+    cancel: &Cancel,
+) -> Result<Solved> {
+    let problem_id = solver.problem_id();
+    println!("Solving... {problem_id}");
+    let problem = Problem::new(problem_id)?;
+    let start = SystemTime::now();
+    let initial_score = solver
+        .initial_solution()
+        .map(|s| solver_sa::score(&problem, problem_id, problem_id.into(), s));
+    let iterations = Shared::new(IterationCounter::default());
+    solver.observe(Box::new(iterations.clone()));
+    // Scores reported by solvers come from incremental updates.
+    let rescore = |solved: &mut Solved| {
+        solved.score =
+            solver_sa::score(&problem, problem_id, problem_id.into(), &solved.solution());
+    };
+    let mut solved = solve_anytime(
+        &mut solver,
+        deadline,
+        PERSIST_INTERVAL,
+        cancel,
+        |improvement| {
+            let mut solved = improvement.solved.clone();
+            rescore(&mut solved);
+            solved.solution().validate(&problem)?;
+            info!(
+                "{problem_id}: new best at {:.1}s: {:.1}",
+                improvement.elapsed.as_secs_f64(),
+                solved.score
+            );
+            solved.save_best_if()
+        },
+    )?;
+    if cancel.is_cancelled() {
+        println!("Interrupted {problem_id}. Keeping the best so far");
+    }
+    solved.validate()?;
+    rescore(&mut solved);
+    println!("Solved {problem_id}. score: {}", solved.score);
+    // Only SA runs the blocker phase. The others have no blocker gain.
+    let breakdown = solved.breakdown.unwrap_or_else(|| {
+        LocalState::new(&problem, problem_id, problem_id.into(), &solved.solution()).breakdown(0.0)
+    });
+    println!("{breakdown}");
+    let solution_path = solved.save_solution()?;
+    insert_run(&Run {
+        problem_id,
+        solver: solved.solver_name.clone(),
+        params: solver.params(),
+        seed: solver.seed(),
+        start: unix_time(start),
+        end: unix_time(SystemTime::now()),
+        iterations: iterations.lock().iterations(),
+        initial_score,
+        score: solved.score,
+        solution_path,
+    })?;
     solved.save_best_if()?;
+    elite::offer(problem_id, &problem, solved.score, &solved.solution())?;
     solved.draw()?;
-    Ok(())
+    Ok(solved)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    // Reports the scores as new bests in turn and ends with the last, or once
+    // cancelled if `until_cancelled`.
+    struct Steps {
+        scores: Vec<Score>,
+        until_cancelled: bool,
+        observer: Option<Box<dyn ProgressObserver + Send>>,
+    }
+
+    impl Solver for Steps {
+        fn problem_id(&self) -> ProblemId {
+            0
+        }
+
+        fn name(&self) -> String {
+            "steps".to_string()
+        }
+
+        fn seed(&self) -> u64 {
+            0
+        }
+
+        fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+            let solution = Solution::example()?;
+            for score in &self.scores {
+                if let Some(observer) = &mut self.observer {
+                    observer.new_best(0, *score, &solution)?;
+                }
+            }
+            while self.until_cancelled && !cancel.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok(Solved {
+                problem_id: 0,
+                solver_name: self.name(),
+                score: *self.scores.last().unwrap(),
+                placements: solution.placements,
+                volumes: solution.volumes,
+                breakdown: None,
+            })
+        }
+
+        fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+            self.observer = Some(observer);
+        }
+    }
+
+    #[test]
+    fn solve_anytime_streams_improvements() -> Result<()> {
+        let mut solver = Steps {
+            scores: vec![1.0, 3.0, 2.0, 4.0, 4.0],
+            until_cancelled: false,
+            observer: None,
+        };
+        let mut scores = vec![];
+        let solved = solve_anytime(
+            &mut solver,
+            Duration::MAX,
+            Duration::ZERO,
+            &Cancel::new(),
+            |improvement| {
+                scores.push(improvement.solved.score);
+                Ok(())
+            },
+        )?;
+        assert_eq!(solved.score, 4.0);
+        assert_eq!(scores, [1.0, 3.0, 4.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn solve_anytime_throttles_improvements() -> Result<()> {
+        let mut solver = Steps {
+            scores: vec![1.0, 3.0, 2.0, 4.0, 4.0],
+            until_cancelled: false,
+            observer: None,
+        };
+        let mut scores = vec![];
+        solve_anytime(
+            &mut solver,
+            Duration::MAX,
+            Duration::MAX,
+            &Cancel::new(),
+            |improvement| {
+                scores.push(improvement.solved.score);
+                Ok(())
+            },
+        )?;
+        // Only the end result is passed on.
+        assert_eq!(scores, [4.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn solve_anytime_stops_on_error() {
+        let mut solver = Steps {
+            scores: vec![1.0],
+            until_cancelled: true,
+            observer: None,
+        };
+        let result = solve_anytime(
+            &mut solver,
+            Duration::MAX,
+            Duration::ZERO,
+            &Cancel::new(),
+            |_| bail!("invalid"),
+        );
+        assert!(result.is_err());
+    }
 
     #[test]
     fn step_by_test() {
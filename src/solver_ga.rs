@@ -0,0 +1,387 @@
+use crate::prelude::*;
+
+use crate::elite;
+use crate::local_state::*;
+use crate::moves::{self, MoveSelector, Outcome};
+use crate::observer::*;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver::*;
+use crate::solver_sa::{End, SolverSa};
+use crate::store;
+use rand::seq::SliceRandom;
+
+// Recombines the elite and best stored solutions. A child takes one parent's musicians
+// on one side of a random line across the stage and the other parent's on the
+// other side, is repaired, and then annealed briefly.
+
+const ANNEAL_TEMP0: f64 = 100.0;
+// Random slots tried for a musician left without a position.
+const SLOT_TRIES: usize = 1_000;
+
+pub struct SolverGa {
+    problem_id: ProblemId,
+    problem: Problem,
+    k: usize,
+    anneal: usize,
+    end: End,
+    population: Vec<Solution>,
+    observers: Observers,
+}
+
+impl SolverGa {
+    // The population is the elite pool, filled up to k with the best in
+    // solution/all, and the initial solution if any. Copies of a solution are
+    // kept once.
+    pub fn new(
+        problem_id: ProblemId,
+        k: usize,
+        anneal: usize,
+        end: End,
+        initial_solution: Option<Solution>,
+        observers: Observers,
+    ) -> Result<Self> {
+        ensure!(k > 0, "empty population");
+        let problem = Problem::new(problem_id)?;
+        let stored = elite::pool(problem_id)?
+            .into_iter()
+            .chain(Solution::top(problem_id, k)?)
+            .map(|(_, solution)| solution);
+        let mut population = distinct(stored)?;
+        population.truncate(k);
+        population.extend(initial_solution);
+        let mut population = distinct(population)?;
+        if population.is_empty() {
+            population.push(SolverSa::initial_solution(&problem));
+        }
+        Ok(Self {
+            problem_id,
+            problem,
+            k,
+            anneal,
+            end,
+            population,
+            observers,
+        })
+    }
+}
+
+// The first of each solution, by content hash.
+fn distinct(solutions: impl IntoIterator<Item = Solution>) -> Result<Vec<Solution>> {
+    let mut seen = HashSet::new();
+    let mut distinct = vec![];
+    for solution in solutions {
+        if seen.insert(store::content(&solution)?.0) {
+            distinct.push(solution);
+        }
+    }
+    Ok(distinct)
+}
+
+// Parent a's musicians on the positive side of the line, parent b's elsewhere.
+// Musicians left without a position go to random free slots.
+fn crossover(
+    problem: &Problem,
+    slots: &[Point],
+    a: &Solution,
+    b: &Solution,
+    rng: &mut StdRng,
+) -> Option<Solution> {
+    let origin = problem.random_point_on_stage(rng);
+    let angle = rng.random_range(0.0f64..2.0 * std::f64::consts::PI);
+    let side = |p: &Point| (p.x - origin.x) * angle.cos() + (p.y - origin.y) * angle.sin() > 0.0;
+
+    // Positions for each instrument.
+    let mut positions = HashMap::<Instrument, Vec<Point>>::new();
+    let from_a = a.placements.iter().enumerate().filter(|(_, p)| side(p));
+    let kept = from_a.clone().map(|(_, p)| *p).collect::<Vec<_>>();
+    for (i, p) in from_a {
+        positions.entry(problem.musicians[i]).or_default().push(*p);
+    }
+    for (i, p) in b.placements.iter().enumerate() {
+        let collides = kept
+            .iter()
+            .any(|q| p.distance_squared(*q) < MUSICIAN_RADIUS_2 + EPS);
+        if !side(p) && !collides {
+            positions.entry(problem.musicians[i]).or_default().push(*p);
+        }
+    }
+
+    // Too many of an instrument: drop some. Too few: the rest wait.
+    let mut placements = vec![None; problem.musicians.len()];
+    let mut classes = HashMap::<Instrument, Vec<usize>>::new();
+    for (i, inst) in problem.musicians.iter().enumerate() {
+        classes.entry(*inst).or_default().push(i);
+    }
+    for (inst, musicians) in classes {
+        let mut ps = positions.remove(&inst).unwrap_or_default();
+        ps.shuffle(rng);
+        for (i, p) in musicians.into_iter().zip(ps) {
+            placements[i] = Some(p);
+        }
+    }
+
+    let mut placed = placements.iter().flatten().copied().collect::<Vec<_>>();
+    for placement in placements.iter_mut().filter(|p| p.is_none()) {
+        let free = (0..SLOT_TRIES)
+            .map(|_| &slots[rng.random_range(0..slots.len())])
+            .find(|p| {
+                placed
+                    .iter()
+                    .all(|q| p.distance_squared(*q) >= MUSICIAN_RADIUS_2 + EPS)
+            })?;
+        *placement = Some(*free);
+        placed.push(*free);
+    }
+
+    Some(Solution {
+        placements: placements.into_iter().map(Option::unwrap).collect(),
+        volumes: vec![10.0; problem.musicians.len()],
+    })
+}
+
+// A short anneal with the SA moves, from temperature ANNEAL_TEMP0 down to 0.
+fn anneal(st: &mut LocalState, iterations: usize, rng: &mut StdRng) {
+    // Moves are scored at the best volumes.
+    st.rebase_score();
+    let mut moves = MoveSelector::new();
+    for niter in 0..iterations {
+        let temp = ANNEAL_TEMP0 * (1.0 - niter as f64 / iterations as f64);
+        let sc = st.score;
+        let kind = moves.select(rng);
+        match moves::apply(kind, st, None, rng) {
+            Outcome::Collided => {
+                st.rollback();
+                moves.collided(kind);
+            }
+            Outcome::Wasted => moves.wasted(kind),
+            Outcome::Applied => {
+                let delta = st.score - sc;
+                let accepted = delta >= 0.0 || (delta / temp).exp() > rng.random_range(0.0..1.0);
+                moves.evaluated(kind, delta, accepted);
+                if accepted {
+                    st.commit();
+                } else {
+                    st.rollback();
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run_ga(
+    name: &str,
+    rng: &mut StdRng,
+    problem: &Problem,
+    problem_id: ProblemId,
+    spec: Spec,
+    population: &[Solution],
+    k: usize,
+    anneal_iterations: usize,
+    end: End,
+    observer: &mut dyn ProgressObserver,
+    cancel: &Cancel,
+) -> Result<(Score, Solution)> {
+    ensure!(!population.is_empty(), "empty population");
+    let slots = candidate_slots(problem);
+    let score = |solution: &Solution| LocalState::new(problem, problem_id, spec, solution).score;
+    let mut population = population
+        .iter()
+        .map(|solution| (score(solution), solution.clone()))
+        .collect::<Vec<_>>();
+
+    population.sort_by_key(|(score, _)| std::cmp::Reverse(OrderedFloat(*score)));
+
+    let timer = std::time::Instant::now();
+    let mut niter = 0;
+    let mut nreplace = 0;
+    // The last child, and the best of the population for the observers. A
+    // failed crossover counts as a collision.
+    let mut sc = population[0].0;
+    let mut best_state = LocalState::new(problem, problem_id, spec, &population[0].1);
+    let mut ncollide = 0;
+    let mut nmove = 0;
+
+    macro_rules! progress {
+        () => {
+            Progress {
+                name,
+                problem_id,
+                start_iteration: 0,
+                iteration: niter,
+                elapsed: timer.elapsed(),
+                score: sc,
+                best: population[0].0,
+                temperature: 0.0,
+                accept_rate: nreplace as f64 / 1.0f64.max(nmove as f64),
+                accept_rate_positive: 0.0,
+                accept_rate_negative: 0.0,
+                ncollide,
+                nmove,
+                moves: None,
+                state: &best_state,
+            }
+        };
+    }
+
+    loop {
+        niter += 1;
+        population.sort_by_key(|(score, _)| std::cmp::Reverse(OrderedFloat(*score)));
+        population.truncate(k);
+        if end.done(niter, timer.elapsed()) >= 1.0 || cancel.is_cancelled() {
+            info!(
+                "ga: niter: {niter}, nreplace: {nreplace}, best: {:.1}",
+                population[0].0
+            );
+            observer.finish(&progress!())?;
+            return Ok(population.swap_remove(0));
+        }
+        observer.progress(&progress!())?;
+
+        // Two distinct parents if there are two.
+        let a = rng.random_range(0..population.len());
+        let b = if population.len() > 1 {
+            (a + rng.random_range(1..population.len())) % population.len()
+        } else {
+            a
+        };
+        let Some(child) = crossover(problem, &slots, &population[a].1, &population[b].1, rng)
+        else {
+            ncollide += 1;
+            continue;
+        };
+        let mut st = LocalState::new(problem, problem_id, spec, &child);
+        anneal(&mut st, anneal_iterations, rng);
+        let child = st.to_solution();
+        sc = score(&child);
+        nmove += 1;
+
+        // Replaces the worst, unless it is already there.
+        let worst = population.last().unwrap().0;
+        let duplicate = population.iter().any(|(s, _)| (s - sc).abs() < 1e-6);
+        if !duplicate && (population.len() < k || sc > worst) {
+            debug!("ga: niter: {niter}, child: {sc:.1}, worst: {worst:.1}");
+            if sc > population[0].0 {
+                observer.new_best(problem_id, sc, &child)?;
+                best_state = LocalState::new(problem, problem_id, spec, &child);
+            }
+            population.push((sc, child));
+            nreplace += 1;
+        }
+    }
+}
+
+impl Solver for SolverGa {
+    fn problem_id(&self) -> ProblemId {
+        self.problem_id
+    }
+
+    fn name(&self) -> String {
+        format!("ga-k{}-a{}-{}", self.k, self.anneal, self.end)
+    }
+
+    fn seed(&self) -> u64 {
+        0
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+        let mut rng = SeedableRng::from_seed([0; 32]);
+
+        let (score, solution) = run_ga(
+            &self.name(),
+            &mut rng,
+            &self.problem,
+            self.problem_id,
+            self.problem_id.into(),
+            &self.population,
+            self.k,
+            self.anneal,
+            self.end,
+            &mut self.observers,
+            cancel,
+        )?;
+        let Solution {
+            placements,
+            volumes,
+        } = solution;
+        Ok(Solved {
+            problem_id: self.problem_id,
+            solver_name: self.name(),
+            score,
+            placements,
+            volumes,
+            breakdown: None,
+        })
+    }
+
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        self.observers.push(observer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ga_example_problem() -> Result<()> {
+        let problem = Problem::example()?;
+        let a = Solution::example()?;
+        let b = Solution {
+            placements: vec![
+                Point::new(1400.0, 100.0),
+                Point::new(1200.0, 150.0),
+                Point::new(1300.0, 150.0),
+            ],
+            volumes: vec![10.0; 3],
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let initial = crate::solver_sa::score(&problem, 0, Spec::V1, &a);
+        let (score, solution) = run_ga(
+            "test-ga",
+            &mut rng,
+            &problem,
+            0,
+            Spec::V1,
+            &[a, b],
+            4,
+            50,
+            End::MaxIteration(20),
+            &mut Observers::default(),
+            &Cancel::new(),
+        )?;
+        solution.validate(&problem)?;
+        assert!(score >= initial);
+        Ok(())
+    }
+
+    #[test]
+    fn anneal_keeps_exact_score() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        // Without a move, the score is still the one `new` gave.
+        for iterations in [0, 200] {
+            let mut rng = StdRng::seed_from_u64(0);
+            let mut st = LocalState::new(&problem, 0, Spec::V1, &solution);
+            anneal(&mut st, iterations, &mut rng);
+            assert_relative_eq!(
+                st.score,
+                crate::solver_sa::score(&problem, 0, Spec::V1, &st.to_solution()),
+                max_relative = 1e-9
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn distinct_test() -> Result<()> {
+        let a = Solution::example()?;
+        let mut b = a.clone();
+        b.volumes[0] = 5.0;
+        let population = distinct([a.clone(), b.clone(), a.clone(), b])?;
+        assert_eq!(population.len(), 2);
+        assert_eq!(population[1].volumes[0], 5.0);
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+use crate::prelude::*;
+
+use crate::local_state::*;
+use crate::moves::{self, MoveSelector, Outcome};
+use crate::observer::*;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver::*;
+use crate::solver_sa::{End, SolverSa};
+
+// Late-acceptance hill climbing with the SA moves. A move is accepted if it is
+// no worse than the current score or than the score `length` iterations ago.
+// Unlike temp0, the length doesn't depend on the scale of the scores.
+
+const REBUILD_INTERVAL: usize = 100_000;
+
+pub struct SolverLahc {
+    problem_id: ProblemId,
+    problem: Problem,
+    length: usize,
+    end: End,
+    initial_solution: Solution,
+    observers: Observers,
+}
+
+impl SolverLahc {
+    pub fn new(
+        problem_id: ProblemId,
+        length: usize,
+        end: End,
+        initial_solution: Option<Solution>,
+        observers: Observers,
+    ) -> Result<Self> {
+        ensure!(length > 0, "empty history");
+        let problem = Problem::new(problem_id)?;
+        let initial_solution =
+            initial_solution.unwrap_or_else(|| SolverSa::initial_solution(&problem));
+        Ok(Self {
+            problem_id,
+            problem,
+            length,
+            end,
+            initial_solution,
+            observers,
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run_lahc(
+    name: &str,
+    rng: &mut StdRng,
+    problem: &Problem,
+    problem_id: ProblemId,
+    spec: Spec,
+    solution: &Solution,
+    length: usize,
+    end: End,
+    observer: &mut dyn ProgressObserver,
+    cancel: &Cancel,
+) -> Result<(Score, Solution)> {
+    let mut st = LocalState::new(problem, problem_id, spec, solution);
+    // Moves are scored at the best volumes.
+    st.rebase_score();
+    let mut sc = st.score;
+    let mut best = sc;
+    let mut best_solution = st.to_solution();
+    let mut history = vec![sc; length];
+
+    let mut moves = MoveSelector::new();
+    let timer = std::time::Instant::now();
+    let mut niter = 0;
+    let mut naccept = 0;
+
+    // As in SA, for the observers.
+    let mut naccept_positive = 0;
+    let mut naccept_negative = 0;
+    let mut ntotal = 0;
+    let mut ncollide = 0;
+    let mut nmove = 0;
+    let mut accept_rates = (0.0, 0.0, 0.0);
+
+    macro_rules! progress {
+        () => {
+            Progress {
+                name,
+                problem_id,
+                start_iteration: 0,
+                iteration: niter,
+                elapsed: timer.elapsed(),
+                score: sc,
+                best,
+                temperature: 0.0,
+                accept_rate: accept_rates.0,
+                accept_rate_positive: accept_rates.1,
+                accept_rate_negative: accept_rates.2,
+                ncollide,
+                nmove,
+                moves: Some(&moves),
+                state: &st,
+            }
+        };
+    }
+
+    loop {
+        niter += 1;
+
+        if niter % 10 == 0 {
+            observer.progress(&progress!())?;
+        }
+
+        if niter % 1_000 == 0 && (end.done(niter, timer.elapsed()) >= 1.0 || cancel.is_cancelled())
+        {
+            info!("lahc: niter: {niter}, best: {best:.1}, naccept: {naccept}");
+            moves.log_summary();
+            observer.finish(&progress!())?;
+            return Ok((best, best_solution));
+        }
+
+        if niter % 10_000 == 0 {
+            let n = 1.0f64.max(ntotal as f64);
+            accept_rates = (
+                (naccept_positive + naccept_negative) as f64 / n,
+                naccept_positive as f64 / n,
+                naccept_negative as f64 / n,
+            );
+            naccept_positive = 0;
+            naccept_negative = 0;
+            ntotal = 0;
+        }
+
+        if niter % REBUILD_INTERVAL == 0 {
+            info!("lahc: niter: {niter}, sc: {sc:.1}, best: {best:.1}, naccept: {naccept}");
+            st = LocalState::new(problem, problem_id, spec, &st.to_solution());
+            sc = st.score;
+        }
+
+        let kind = moves.select(rng);
+        match moves::apply(kind, &mut st, None, rng) {
+            Outcome::Applied => {}
+            Outcome::Collided => {
+                st.rollback();
+                ncollide += 1;
+                moves.collided(kind);
+                continue;
+            }
+            Outcome::Wasted => {
+                moves.wasted(kind);
+                continue;
+            }
+        }
+
+        nmove += 1;
+        ntotal += 1;
+
+        let sc2 = st.score;
+        let v = niter % length;
+        let accepted = sc2 >= sc || sc2 >= history[v];
+        moves.evaluated(kind, sc2 - sc, accepted);
+        if accepted {
+            naccept += 1;
+            if sc2 >= sc {
+                naccept_positive += 1;
+            } else {
+                naccept_negative += 1;
+            }
+            st.commit();
+            sc = sc2;
+            if sc > best {
+                best = sc;
+                best_solution = st.to_solution();
+                observer.new_best(problem_id, best, &best_solution)?;
+            }
+        } else {
+            st.rollback();
+        }
+        history[v] = sc;
+    }
+}
+
+impl Solver for SolverLahc {
+    fn problem_id(&self) -> ProblemId {
+        self.problem_id
+    }
+
+    fn name(&self) -> String {
+        format!("lahc-l{}-{}", self.length, self.end)
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+        let mut rng = SeedableRng::from_seed([0; 32]);
+
+        let (score, solution) = run_lahc(
+            &self.name(),
+            &mut rng,
+            &self.problem,
+            self.problem_id,
+            self.problem_id.into(),
+            &self.initial_solution,
+            self.length,
+            self.end,
+            &mut self.observers,
+            cancel,
+        )?;
+        let Solution {
+            placements,
+            volumes,
+        } = solution;
+        Ok(Solved {
+            problem_id: self.problem_id,
+            solver_name: self.name(),
+            score,
+            placements,
+            volumes,
+            breakdown: None,
+        })
+    }
+
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    fn seed(&self) -> u64 {
+        0
+    }
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        Some(&self.initial_solution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Counts {
+        progress: usize,
+        finished: bool,
+    }
+
+    impl ProgressObserver for Counts {
+        fn progress(&mut self, _progress: &Progress) -> Result<()> {
+            self.progress += 1;
+            Ok(())
+        }
+
+        fn finish(&mut self, _progress: &Progress) -> Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn lahc_example_problem() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let initial = crate::solver_sa::score(&problem, 0, Spec::V1, &solution);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts = Counts::default();
+        let (score, solution) = run_lahc(
+            "test-lahc",
+            &mut rng,
+            &problem,
+            0,
+            Spec::V1,
+            &solution,
+            50,
+            End::MaxIteration(20_000),
+            &mut counts,
+            &Cancel::new(),
+        )?;
+        assert_eq!(counts.progress, 2_000);
+        assert!(counts.finished);
+        solution.validate(&problem)?;
+        assert!(score >= initial);
+        assert_relative_eq!(
+            score,
+            crate::solver_sa::score(&problem, 0, Spec::V1, &solution),
+            max_relative = 1e-9
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,427 @@
+use rand::rngs::StdRng;
+
+use crate::prelude::*;
+
+use crate::local_state::*;
+use crate::observer::*;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver::*;
+use crate::solver_sa::{End, SolverSa};
+use rand::seq::{IndexedRandom, SliceRandom};
+use rand::Rng;
+
+// Large neighbourhood search: remove k musicians and reinsert them one by one.
+// Removed musicians are off the stage until reinserted, so they neither score
+// nor block the ones placed before them.
+
+#[derive(Copy, Clone, Debug, derive_more::Display)]
+pub enum Repair {
+    // Each removed musician goes to the best of a sample of free slots.
+    #[display("greedy")]
+    Greedy,
+    // Removed musicians go to random free slots, then a short SA moves them.
+    #[display("local-sa")]
+    LocalSa,
+}
+
+#[derive(Copy, Clone, Debug, derive_more::Display)]
+pub enum Acceptance {
+    #[display("anneal-{temp0}")]
+    Annealing { temp0: f64 },
+    // Accept if not worse than the score `length` iterations ago.
+    #[display("late-{length}")]
+    LateAcceptance { length: usize },
+}
+
+const SLOT_SAMPLES: usize = 32;
+const LOCAL_SA_ITERATIONS: usize = 50;
+// Temperature of the local SA repair under late acceptance, as a fraction of
+// the current score.
+const LATE_REPAIR_TEMP: f64 = 1e-4;
+
+pub struct SolverLns {
+    problem_id: ProblemId,
+    problem: Problem,
+    k: usize,
+    repair: Repair,
+    acceptance: Acceptance,
+    end: End,
+    initial_solution: Solution,
+    observers: Observers,
+}
+
+impl SolverLns {
+    pub fn new(
+        problem_id: ProblemId,
+        k: usize,
+        repair: Repair,
+        acceptance: Acceptance,
+        end: End,
+        initial_solution: Option<Solution>,
+        observers: Observers,
+    ) -> Result<Self> {
+        let problem = Problem::new(problem_id)?;
+        let initial_solution =
+            initial_solution.unwrap_or_else(|| SolverSa::initial_solution(&problem));
+        Ok(Self {
+            problem_id,
+            problem,
+            k,
+            repair,
+            acceptance,
+            end,
+            initial_solution,
+            observers,
+        })
+    }
+}
+
+// Removes the musicians near a random point, or a random subset of one
+// instrument, and returns them.
+fn destroy(st: &mut LocalState, k: usize, rng: &mut StdRng) -> Vec<usize> {
+    let mut removed = if rng.random_bool(0.5) {
+        let p = st.problem.random_point_on_stage(rng);
+        st.neighbors(p, Coord::INFINITY)
+    } else {
+        let mut class = st.classes[rng.random_range(0..st.classes.len())].clone();
+        class.shuffle(rng);
+        class
+    };
+    removed.truncate(k);
+    removed.shuffle(rng);
+    for &i in &removed {
+        st.remove(i);
+    }
+    removed
+}
+
+// Free slots for the removed musician i.
+fn sample_slots(st: &LocalState, slots: &[Point], i: usize, rng: &mut StdRng) -> Vec<Point> {
+    (0..SLOT_SAMPLES)
+        .map(|_| slots[rng.random_range(0..slots.len())])
+        .filter(|p| !st.collides(i, *p))
+        .collect()
+}
+
+// Reinserts the removed musicians in order. Returns false if some musician
+// could not be reinserted.
+fn repair(
+    st: &mut LocalState,
+    slots: &[Point],
+    removed: &[usize],
+    repair: Repair,
+    temp: f64,
+    rng: &mut StdRng,
+) -> bool {
+    for &i in removed {
+        // Where it was is a candidate too.
+        let mut candidates = sample_slots(st, slots, i, rng);
+        if !st.collides(i, st.place[i]) {
+            candidates.push(st.place[i]);
+        }
+        let to = match repair {
+            Repair::Greedy => candidates
+                .into_iter()
+                .map(|p| (OrderedFloat(st.delta_insert(i, p)), p))
+                .max_by_key(|(delta, _)| *delta)
+                .map(|(_, p)| p),
+            Repair::LocalSa => candidates.choose(rng).copied(),
+        };
+        let Some(to) = to else {
+            return false;
+        };
+        st.insert(i, to);
+    }
+
+    if let Repair::LocalSa = repair {
+        for _ in 0..LOCAL_SA_ITERATIONS * removed.len() {
+            let i = removed[rng.random_range(0..removed.len())];
+            let p0 = st.place[i];
+            let dist = 20.0 * rng.random_range(0.0f64..1.0).powi(2);
+            let angle = rng.random_range(0.0f64..2.0 * std::f64::consts::PI);
+            let p = Point::new(p0.x + dist * angle.cos(), p0.y + dist * angle.sin());
+            if st.collides(i, p) {
+                continue;
+            }
+            let delta = st.delta_move(i, p);
+            if delta >= 0.0 || (delta / temp).exp() > rng.random_range(0.0..1.0) {
+                st.move_to(i, p);
+            }
+        }
+    }
+    true
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run_lns(
+    name: &str,
+    rng: &mut StdRng,
+    problem: &Problem,
+    problem_id: ProblemId,
+    spec: Spec,
+    solution: &Solution,
+    k: usize,
+    repair_kind: Repair,
+    acceptance: Acceptance,
+    end: End,
+    observer: &mut dyn ProgressObserver,
+    cancel: &Cancel,
+) -> Result<(Score, Solution)> {
+    let slots = candidate_slots(problem);
+    ensure!(!slots.is_empty(), "no slots on the stage");
+
+    let mut st = LocalState::new(problem, problem_id, spec, solution);
+    st.rebase_score();
+    let mut sc = st.score;
+    let mut best = sc;
+    let mut best_solution = st.to_solution();
+
+    let mut history = match acceptance {
+        Acceptance::LateAcceptance { length } => vec![sc; length.max(1)],
+        Acceptance::Annealing { .. } => vec![],
+    };
+    let timer = std::time::Instant::now();
+    let mut naccept = 0;
+    let mut niter = 0;
+    let mut temp = 0.0;
+
+    // For the observers. A failed repair counts as a collision.
+    let mut naccept_positive = 0;
+    let mut naccept_negative = 0;
+    let mut ntotal = 0;
+    let mut ncollide = 0;
+    let mut nmove = 0;
+    let mut accept_rates = (0.0, 0.0, 0.0);
+
+    macro_rules! progress {
+        () => {
+            Progress {
+                name,
+                problem_id,
+                start_iteration: 0,
+                iteration: niter,
+                elapsed: timer.elapsed(),
+                score: sc,
+                best,
+                temperature: temp,
+                accept_rate: accept_rates.0,
+                accept_rate_positive: accept_rates.1,
+                accept_rate_negative: accept_rates.2,
+                ncollide,
+                nmove,
+                moves: None,
+                state: &st,
+            }
+        };
+    }
+
+    loop {
+        niter += 1;
+
+        let done = end.done(niter, timer.elapsed());
+        if done >= 1.0 || cancel.is_cancelled() {
+            info!("lns: niter: {niter}, best: {best:.1}, naccept: {naccept}");
+            observer.finish(&progress!())?;
+            return Ok((best, best_solution));
+        }
+
+        observer.progress(&progress!())?;
+
+        if niter % 1_000 == 0 {
+            info!("lns: niter: {niter}, sc: {sc:.1}, best: {best:.1}, naccept: {naccept}");
+            let solution = st.to_solution();
+            st = LocalState::new(problem, problem_id, spec, &solution);
+            sc = st.score;
+
+            let n = 1.0f64.max(ntotal as f64);
+            accept_rates = (
+                (naccept_positive + naccept_negative) as f64 / n,
+                naccept_positive as f64 / n,
+                naccept_negative as f64 / n,
+            );
+            naccept_positive = 0;
+            naccept_negative = 0;
+            ntotal = 0;
+        }
+
+        temp = match acceptance {
+            Acceptance::Annealing { temp0 } => temp0 * (1.0 - done),
+            // Only used by the local SA repair.
+            Acceptance::LateAcceptance { .. } => LATE_REPAIR_TEMP * sc.abs().max(1.0),
+        };
+
+        let removed = destroy(&mut st, k, rng);
+        if !repair(&mut st, &slots, &removed, repair_kind, temp, rng) {
+            st.rollback();
+            ncollide += 1;
+            continue;
+        }
+        nmove += 1;
+        ntotal += 1;
+
+        let sc2 = st.score;
+        let accepted = match acceptance {
+            Acceptance::Annealing { .. } => {
+                sc2 >= sc || ((sc2 - sc) / temp).exp() > rng.random_range(0.0..1.0)
+            }
+            Acceptance::LateAcceptance { .. } => {
+                let v = niter % history.len();
+                let accepted = sc2 >= sc || sc2 >= history[v];
+                history[v] = if accepted { sc2 } else { sc };
+                accepted
+            }
+        };
+
+        if accepted {
+            naccept += 1;
+            if sc2 >= sc {
+                naccept_positive += 1;
+            } else {
+                naccept_negative += 1;
+            }
+            st.commit();
+            sc = sc2;
+            if sc > best {
+                best = sc;
+                best_solution = st.to_solution();
+                observer.new_best(problem_id, best, &best_solution)?;
+            }
+        } else {
+            st.rollback();
+        }
+    }
+}
+
+impl Solver for SolverLns {
+    fn problem_id(&self) -> ProblemId {
+        self.problem_id
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "lns-k{}-{}-{}-{}",
+            self.k, self.repair, self.acceptance, self.end
+        )
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+        let mut rng = SeedableRng::from_seed([0; 32]);
+
+        let (score, solution) = run_lns(
+            &self.name(),
+            &mut rng,
+            &self.problem,
+            self.problem_id,
+            self.problem_id.into(),
+            &self.initial_solution,
+            self.k,
+            self.repair,
+            self.acceptance,
+            self.end,
+            &mut self.observers,
+            cancel,
+        )?;
+        let Solution {
+            placements,
+            volumes,
+        } = solution;
+        Ok(Solved {
+            problem_id: self.problem_id,
+            solver_name: self.name(),
+            score,
+            placements,
+            volumes,
+            breakdown: None,
+        })
+    }
+
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    fn seed(&self) -> u64 {
+        0
+    }
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        Some(&self.initial_solution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repair_after_removal() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let slots = candidate_slots(&problem);
+        for (seed, repair_kind) in [(0, Repair::Greedy), (1, Repair::LocalSa)] {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut st = LocalState::new(&problem, 0, Spec::V1, &solution);
+            st.rebase_score();
+            let removed = destroy(&mut st, 2, &mut rng);
+
+            // The score is that of the problem without them.
+            let mut without = problem.clone();
+            let mut rest = solution.clone();
+            let mut indices = removed.clone();
+            indices.sort();
+            for &i in indices.iter().rev() {
+                without.musicians.remove(i);
+                rest.placements.remove(i);
+                rest.volumes.remove(i);
+            }
+            let mut expected = LocalState::new(&without, 0, Spec::V1, &rest);
+            expected.rebase_score();
+            assert_relative_eq!(st.score, expected.score, epsilon = 1e-6);
+
+            let repaired = repair(&mut st, &slots, &removed, repair_kind, 100.0, &mut rng);
+            assert!(repaired);
+            assert_relative_eq!(
+                st.score,
+                crate::solver_sa::score(&problem, 0, Spec::V1, &st.to_solution()),
+                max_relative = 1e-9
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lns_example_problem() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        for (repair, acceptance) in [
+            (Repair::Greedy, Acceptance::LateAcceptance { length: 10 }),
+            (Repair::LocalSa, Acceptance::Annealing { temp0: 100.0 }),
+            (Repair::LocalSa, Acceptance::LateAcceptance { length: 10 }),
+        ] {
+            let mut rng = StdRng::seed_from_u64(0);
+            let initial = crate::solver_sa::score(&problem, 0, Spec::V1, &solution);
+            let (score, solution) = run_lns(
+                "test-lns",
+                &mut rng,
+                &problem,
+                0,
+                Spec::V1,
+                &solution,
+                2,
+                repair,
+                acceptance,
+                End::MaxIteration(200),
+                &mut Observers::default(),
+                &Cancel::new(),
+            )?;
+            assert!(score >= initial);
+            for (i, p) in solution.placements.iter().enumerate() {
+                assert!(problem.on_stage(*p));
+                for q in &solution.placements[i + 1..] {
+                    assert!(p.distance_squared(*q) > MUSICIAN_RADIUS_2);
+                }
+            }
+        }
+        Ok(())
+    }
+}
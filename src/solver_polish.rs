@@ -0,0 +1,285 @@
+use crate::prelude::*;
+
+use crate::local_state::*;
+use crate::observer::*;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver::*;
+use crate::solver_sa::SolverSa;
+
+// Deterministic coordinate descent. Each musician tries small moves in eight
+// directions, clamped to the stage so that it can slide along an edge, and
+// keeps only strict improvements. The step halves whenever a pass at the
+// current step finds nothing. A pass is kept only if it improves the exact
+// score.
+
+const D: Coord = std::f64::consts::FRAC_1_SQRT_2;
+const DIRECTIONS: [(Coord, Coord); 8] = [
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (D, D),
+    (D, -D),
+    (-D, D),
+    (-D, -D),
+];
+
+// Gains below this are float noise from the incremental update.
+const MIN_GAIN: Score = 1e-6;
+
+pub struct SolverPolish {
+    problem_id: ProblemId,
+    problem: Problem,
+    step: Coord,
+    min_step: Coord,
+    initial_solution: Solution,
+    observers: Observers,
+}
+
+impl SolverPolish {
+    // Polishes the best solution unless given another one.
+    pub fn new(
+        problem_id: ProblemId,
+        step: Coord,
+        min_step: Coord,
+        initial_solution: Option<Solution>,
+        observers: Observers,
+    ) -> Result<Self> {
+        ensure!(step >= min_step && min_step > 0.0, "invalid steps");
+        let problem = Problem::new(problem_id)?;
+        let initial_solution = match initial_solution {
+            Some(solution) => solution,
+            None => {
+                Solution::best(problem_id).unwrap_or_else(|_| SolverSa::initial_solution(&problem))
+            }
+        };
+        Ok(Self {
+            problem_id,
+            problem,
+            step,
+            min_step,
+            initial_solution,
+            observers,
+        })
+    }
+}
+
+fn clamp_to_stage(problem: &Problem, p: Point) -> Point {
+    let [x0, y0] = problem.stage_bottom_left;
+    Point::new(
+        p.x.clamp(
+            x0 + MUSICIAN_RADIUS,
+            x0 + problem.stage_width - MUSICIAN_RADIUS,
+        ),
+        p.y.clamp(
+            y0 + MUSICIAN_RADIUS,
+            y0 + problem.stage_height - MUSICIAN_RADIUS,
+        ),
+    )
+}
+
+// One pass over all musicians. Returns the number of moves kept.
+fn pass(st: &mut LocalState, step: Coord) -> usize {
+    let mut nmove = 0;
+    for i in 0..st.place.len() {
+        loop {
+            let p0 = st.place[i];
+            let candidates = DIRECTIONS
+                .iter()
+                .map(|(dx, dy)| {
+                    clamp_to_stage(st.problem, Point::new(p0.x + step * dx, p0.y + step * dy))
+                })
+                .filter(|p| p.distance_squared(p0) > 0.0 && !st.collides(i, *p))
+                .collect::<Vec<_>>();
+            let best = candidates
+                .into_iter()
+                .map(|p| (OrderedFloat(st.delta_move(i, p)), p))
+                .max_by_key(|(delta, _)| *delta);
+            match best {
+                Some((delta, p)) if delta.0 > MIN_GAIN => {
+                    st.move_to(i, p);
+                    st.commit();
+                    nmove += 1;
+                }
+                _ => break,
+            }
+        }
+    }
+    nmove
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run_polish(
+    name: &str,
+    problem: &Problem,
+    problem_id: ProblemId,
+    spec: Spec,
+    solution: &Solution,
+    step: Coord,
+    min_step: Coord,
+    observer: &mut dyn ProgressObserver,
+    cancel: &Cancel,
+) -> Result<(Score, Solution)> {
+    let mut st = LocalState::new(problem, problem_id, spec, solution);
+    let initial = st.score;
+    // Moves are scored at the best volumes.
+    st.rebase_score();
+    let timer = std::time::Instant::now();
+    // A pass is an iteration for the observers.
+    let mut niter = 0;
+    let mut nmove = 0;
+
+    macro_rules! progress {
+        () => {
+            Progress {
+                name,
+                problem_id,
+                start_iteration: 0,
+                iteration: niter,
+                elapsed: timer.elapsed(),
+                score: st.score,
+                best: st.score,
+                temperature: 0.0,
+                accept_rate: 0.0,
+                accept_rate_positive: 0.0,
+                accept_rate_negative: 0.0,
+                ncollide: 0,
+                nmove,
+                moves: None,
+                state: &st,
+            }
+        };
+    }
+
+    let mut step = step;
+    while step >= min_step && !cancel.is_cancelled() {
+        niter += 1;
+        let score = st.score;
+        let before = st.to_solution();
+        let n = pass(&mut st, step);
+        nmove += n;
+        // Drop the drift of the incremental scores.
+        st = LocalState::new(problem, problem_id, spec, &st.to_solution());
+        info!("polish: step: {step}, nmove: {n}, score: {:.1}", st.score);
+        if st.score <= score {
+            // Keep only passes that improve the exact score.
+            if n > 0 {
+                st = LocalState::new(problem, problem_id, spec, &before);
+            }
+            step /= 2.0;
+        } else {
+            observer.new_best(problem_id, st.score, &st.to_solution())?;
+        }
+        observer.progress(&progress!())?;
+    }
+    info!("polish: gain: {:.1}", st.score - initial);
+    observer.finish(&progress!())?;
+    Ok((st.score, st.to_solution()))
+}
+
+impl Solver for SolverPolish {
+    fn problem_id(&self) -> ProblemId {
+        self.problem_id
+    }
+
+    fn name(&self) -> String {
+        format!("polish-{}-{}", self.step, self.min_step)
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+        let (score, solution) = run_polish(
+            &self.name(),
+            &self.problem,
+            self.problem_id,
+            self.problem_id.into(),
+            &self.initial_solution,
+            self.step,
+            self.min_step,
+            &mut self.observers,
+            cancel,
+        )?;
+        let Solution {
+            placements,
+            volumes,
+        } = solution;
+        Ok(Solved {
+            problem_id: self.problem_id,
+            solver_name: self.name(),
+            score,
+            placements,
+            volumes,
+            breakdown: None,
+        })
+    }
+
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    fn seed(&self) -> u64 {
+        0
+    }
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        Some(&self.initial_solution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polish_example_problem() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let initial = crate::solver_sa::score(&problem, 0, Spec::V1, &solution);
+        let polish = |solution: &Solution, step: Coord| {
+            run_polish(
+                "test-polish",
+                &problem,
+                0,
+                Spec::V1,
+                solution,
+                step,
+                0.01,
+                &mut Observers::default(),
+                &Cancel::new(),
+            )
+        };
+        let (score, polished) = polish(&solution, 16.0)?;
+        assert!(score >= initial);
+        assert_eq!(score, crate::solver_sa::score(&problem, 0, Spec::V1, &polished));
+        polished.validate(&problem)?;
+
+        // A local optimum: polishing again changes nothing.
+        let (again, _) = polish(&polished, 0.01)?;
+        assert_eq!(again, score);
+        Ok(())
+    }
+
+    #[test]
+    fn polish_never_loses_exact_score() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        for spec in [Spec::V1, Spec::V2] {
+            let initial = crate::solver_sa::score(&problem, 0, spec, &solution);
+            for (step, min_step) in [(64.0, 1.0), (4.0, 0.5), (0.5, 0.5)] {
+                let (_, polished) = run_polish(
+                    "test-polish",
+                    &problem,
+                    0,
+                    spec,
+                    &solution,
+                    step,
+                    min_step,
+                    &mut Observers::default(),
+                    &Cancel::new(),
+                )?;
+                assert!(crate::solver_sa::score(&problem, 0, spec, &polished) >= initial);
+            }
+        }
+        Ok(())
+    }
+}
@@ -2,374 +2,111 @@ use rand::rngs::StdRng;
 
 use crate::prelude::*;
 
-use crate::draw;
+use crate::blocker::*;
+use crate::local_state::*;
+use crate::moves::*;
+use crate::observer::*;
+use crate::pillars::{self, ShadowMap};
 use crate::problem::*;
 use crate::solution::*;
 use crate::solver::*;
 use rand::Rng;
 
-// https://gitlab.com/rafaelbocquet-cpcontests/icfpc23/-/blob/main/cxx/solve.cpp
-
-#[derive(Default)]
-struct AttNode {
-    angle: f64,
-    // Attendee's index.
-    index: usize,
-    nblock: u32,
-    x: Coord,
-    y: Coord,
-}
-
-struct LocalState<'a> {
-    problem_id: ProblemId,
-    problem: &'a Problem,
-    spec: Spec,
-    place: Vec<Point>,
-    q: Vec<Score>,
-    angles: Vec<Vec<AttNode>>,
-    scores: Vec<Score>,
-    score: Score,
-}
-
-// problem.hpp
-
-fn norm_angle(mut angle: f64) -> f64 {
-    while angle < 0.0 {
-        angle += 2.0 * std::f64::consts::PI;
-    }
-    while angle > 2.0 * std::f64::consts::PI {
-        angle -= 2.0 * std::f64::consts::PI;
-    }
-    angle
-}
-
 // Score doesn't match judge's one because volumes or q are muliplied at last.
 pub fn score(problem: &Problem, problem_id: ProblemId, spec: Spec, solution: &Solution) -> Score {
     let st = LocalState::new(problem, problem_id, spec, solution);
     st.score
 }
 
-impl<'a> LocalState<'a> {
-    fn new(problem: &'a Problem, problem_id: ProblemId, spec: Spec, solution: &Solution) -> Self {
-        let nm = solution.placements.len();
-        let natt = problem.attendees.len();
-
-        let place = solution.placements.clone();
-
-        let angles = (0..nm)
-            .map(|_| {
-                (0..natt)
-                    .map(|j| AttNode {
-                        angle: 0.0,
-                        index: j,
-                        nblock: 0,
-                        x: problem.attendees[j].x,
-                        y: problem.attendees[j].y,
-                    })
-                    .collect()
-            })
-            .collect();
-
-        let mut state = LocalState {
-            problem_id,
-            problem,
-            spec,
-            place,
-            q: vec![1.0; nm],
-            angles,
-            scores: vec![0.0; nm],
-            score: 0.0,
-        };
+// Iterations of the blocker phase after annealing.
+const BLOCKER_ITERATIONS: usize = 5_000;
 
-        state.cal_q();
-
-        for i in 0..nm {
-            state.make_angles(i);
-        }
+#[derive(Copy, Clone, derive_more::Display)]
+pub enum End {
+    #[display("iter-{_0}")]
+    MaxIteration(usize),
+    #[display("duration-{}", _0.as_secs())]
+    MaxDuration(std::time::Duration),
+}
 
-        state.update_score(Some(&solution.volumes));
-        // state.update_score(None);
-        state
-    }
+// "500ms", "60s", "5m" or "2h" for a duration, a plain number for iterations.
+impl std::str::FromStr for End {
+    type Err = anyhow::Error;
 
-    fn update_score(&mut self, volumes: Option<&[f64]>) {
-        self.score = if let Some(volumes) = volumes {
-            (0..self.scores.len())
-                .map(|i| {
-                    // self.q[i] * self.scores[i] * volumes[i]
-                    let a = self.q[i] * self.scores[i];
-                    // // println!("m: {i}: score: {a}, volume: {}", volumes[i]);
-                    a * volumes[i]
-                })
-                .sum()
-        } else {
-            (0..self.scores.len())
-                .map(|i| (self.q[i] * self.scores[i]).max(0.0) * 10.0)
-                .sum()
+    fn from_str(s: &str) -> Result<Self> {
+        let secs = |n: &str, unit: u64| -> Result<End> {
+            Ok(End::MaxDuration(std::time::Duration::from_secs(
+                n.parse::<u64>()? * unit,
+            )))
         };
-    }
-
-    fn cal_q(&mut self) {
-        if self.is_full_round() {
-            for i in 0..self.q.len() {
-                for j in 0..self.q.len() {
-                    if i != j && self.problem.musicians[i] == self.problem.musicians[j] {
-                        self.q[i] += 1.0 / self.place[i].distance(self.place[j]);
-                    }
-                }
-            }
-        }
-    }
-
-    fn to_solution(&self) -> Solution {
-        let volumes = self
-            .scores
-            .iter()
-            .map(|score| if *score <= 1e-6 { 0.0 } else { 10.0 })
-            .collect();
-        Solution {
-            placements: self.place.clone(),
-            volumes,
-        }
-    }
-
-    fn report_progress(&self) -> Result<()> {
-        // Save svg for reporting.
-        let solution = self.to_solution();
-        draw::draw_solution(
-            self.problem_id,
-            &solution,
-            project_path(format!("draw/wip/{}.svg", self.problem_id)),
-        )
-    }
-
-    fn is_full_round(&self) -> bool {
-        matches!(self.spec, Spec::V2)
-    }
-
-    fn make_angles(&mut self, i: usize) {
-        for j in 0..self.problem.attendees.len() {
-            let a = &mut self.angles[i][j];
-            let x = a.x - self.place[i].x;
-            let y = a.y - self.place[i].y;
-            a.angle = norm_angle(y.atan2(x));
-            a.nblock = 0;
-        }
-        // Sort attendeees by angle from musician i to attendee.
-        self.angles[i].sort_by_key(|a| OrderedFloat(a.angle));
-
-        self.scores[i] = 0.0;
-        for j in 0..self.problem.attendees.len() {
-            let d2 = self.place[i].distance_squared(self.problem.attendees[j].point());
-            self.scores[i] +=
-                1e6 * self.problem.attendees[j].tastes[self.problem.musicians[i]] / d2;
-        }
-
-        for j in 0..self.place.len() {
-            if i != j {
-                self.add_blocks(i, j);
-            }
-        }
-
-        if self.is_full_round() {
-            self.add_pillars(i);
-        }
-    }
-
-    fn add_blocks(&mut self, i: usize, j: usize) {
-        let [r1, r2] = self.blocks_range(i, j);
-        for k in r1.into_iter().chain(r2.into_iter()) {
-            if self.angles[i][k].nblock == 0 {
-                let d2 = self.place[i]
-                    .distance_squared(self.problem.attendees[self.angles[i][k].index].point());
-                self.scores[i] -= 1e6
-                    * self.problem.attendees[self.angles[i][k].index].tastes
-                        [self.problem.musicians[i]]
-                    / d2;
-            }
-            self.angles[i][k].nblock += 1;
-        }
-    }
-
-    fn rem_blocks(&mut self, i: usize, j: usize) {
-        let [r1, r2] = self.blocks_range(i, j);
-        for k in r1.into_iter().chain(r2.into_iter()) {
-            self.angles[i][k].nblock -= 1;
-            if self.angles[i][k].nblock == 0 {
-                let d2 = self.place[i]
-                    .distance_squared(self.problem.attendees[self.angles[i][k].index].point());
-                self.scores[i] += 1e6
-                    * self.problem.attendees[self.angles[i][k].index].tastes
-                        [self.problem.musicians[i]]
-                    / d2;
-            }
-        }
-    }
-
-    fn add_pillars(&mut self, i: usize) {
-        for j in 0..self.problem.pillars.len() {
-            let dp2 = self.place[i].distance_squared(self.problem.pillars[j].center_point());
-            let [r1, r2] = self.pillars_range(i, j);
-            for k in r1.into_iter().chain(r2.into_iter()) {
-                let d2 = self.place[i]
-                    .distance_squared(self.problem.attendees[self.angles[i][k].index].point());
-                if d2 > dp2 {
-                    if self.angles[i][k].nblock == 0 {
-                        self.scores[i] -= 1e6
-                            * self.problem.attendees[self.angles[i][k].index].tastes
-                                [self.problem.musicians[i]]
-                            / d2;
-                    }
-                    self.angles[i][k].nblock += 1;
-                }
-            }
-        }
-    }
-
-    fn find_index(&self, angles: &[AttNode], angle: f64) -> usize {
-        let mut left = 0;
-        let mut right = angles.len();
-
-        while left < right {
-            let mid = left + (right - left) / 2;
-            if angles[mid].angle < angle {
-                left = mid + 1;
-            } else {
-                right = mid
-            }
-        }
-        left
-    }
-
-    fn blocks_range(&self, i: usize, j: usize) -> [Range<usize>; 2] {
-        let d = self.place[i].distance(self.place[j]);
-        let angle = (self.place[j].y - self.place[i].y).atan2(self.place[j].x - self.place[i].x);
-        let alpha = (BLOCK_RADIUS / d).asin();
-        let angle0 = norm_angle(angle - alpha);
-        let angle1 = norm_angle(angle + alpha);
-        let ix0 = self.find_index(&self.angles[i], angle0);
-        let ix1 = self.find_index(&self.angles[i], angle1);
-        if angle0 < angle1 {
-            assert!(ix0 <= ix1);
-            [(ix0..ix1), 0..0]
+        if let Some(n) = s.strip_suffix("ms") {
+            Ok(End::MaxDuration(std::time::Duration::from_millis(
+                n.parse()?,
+            )))
+        } else if let Some(n) = s.strip_suffix('s') {
+            secs(n, 1)
+        } else if let Some(n) = s.strip_suffix('m') {
+            secs(n, 60)
+        } else if let Some(n) = s.strip_suffix('h') {
+            secs(n, 3_600)
         } else {
-            assert!(ix1 <= ix0);
-            [(ix0..self.problem.attendees.len()), (0..ix1)]
+            Ok(End::MaxIteration(s.parse()?))
         }
     }
+}
 
-    fn pillars_range(&self, i: usize, j: usize) -> [Range<usize>; 2] {
-        let center = self.problem.pillars[j].center_point();
-        let radius = self.problem.pillars[j].radius;
-
-        let d = self.place[i].distance(center);
-        let angle = (center.y - self.place[i].y).atan2(center.x - self.place[i].x);
-        let alpha = (radius / d).asin();
-        let angle0 = norm_angle(angle - alpha);
-        let angle1 = norm_angle(angle + alpha);
-        let ix0 = self.find_index(&self.angles[i], angle0);
-        let ix1 = self.find_index(&self.angles[i], angle1);
-        if angle0 < angle1 {
-            [(ix0..ix1), 0..0]
-        } else {
-            [(ix0..self.problem.attendees.len()), (0..ix1)]
-        }
-    }
-
-    fn do_move(&mut self, i: usize, to: Point) {
-        let nm = self.place.len();
-
-        for j in 0..nm {
-            if i != j {
-                self.rem_blocks(j, i);
-            }
-        }
-
-        if self.is_full_round() {
-            for j in 0..nm {
-                if i != j && self.problem.musicians[i] == self.problem.musicians[j] {
-                    self.q[j] -= 1.0 / self.place[i].distance(self.place[j]);
-                }
-            }
-        }
-
-        self.place[i] = to;
-
-        self.q[i] = 1.0;
-        if self.is_full_round() {
-            for j in 0..nm {
-                if i != j && self.problem.musicians[i] == self.problem.musicians[j] {
-                    let qplus = 1.0 / self.place[i].distance(self.place[j]);
-                    self.q[i] += qplus;
-                    self.q[j] += qplus;
-                }
-            }
-        }
-
-        self.make_angles(i);
-
-        for j in 0..nm {
-            if i != j {
-                self.add_blocks(j, i);
+impl End {
+    // Progress in [0, 1], or beyond 1 once the run is over.
+    pub fn done(&self, niter: usize, elapsed: std::time::Duration) -> f64 {
+        match self {
+            End::MaxIteration(max_iter) => niter as f64 / *max_iter as f64,
+            End::MaxDuration(max_duration) => {
+                elapsed.as_millis() as f64 / max_duration.as_millis() as f64
             }
         }
-
-        self.update_score(None);
     }
+}
 
-    fn do_swap(&mut self, a: usize, b: usize) {
-        let nm = self.place.len();
-
-        if self.is_full_round() {
-            for i in [a, b] {
-                for j in 0..nm {
-                    if i != j && self.problem.musicians[i] == self.problem.musicians[j] {
-                        self.q[j] -= 1.0 / self.place[i].distance(self.place[j]);
-                    }
-                }
-            }
-        }
+// Must be a multiple of the LocalState rebuild interval so that a resumed run
+// starts from the same rebuilt state.
+const CHECKPOINT_INTERVAL: usize = 500_000;
+
+// Everything `run_sa` needs to continue a run exactly where it stopped.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Checkpoint {
+    pub niter: usize,
+    pub elapsed: std::time::Duration,
+    // The RNG is reseeded from this at every checkpoint.
+    pub seed: [u8; 32],
+    pub temp0: f64,
+    pub temp: f64,
+    pub score: Score,
+    pub solution: Solution,
+    pub best: Score,
+    pub best_solution: Solution,
+    pub moves: MoveSelector,
+    pub ncollide: usize,
+    pub nmove: usize,
+}
 
-        self.place.swap(a, b);
-        self.make_angles(a);
-        self.make_angles(b);
-
-        if self.is_full_round() {
-            self.q[a] = 1.0;
-            self.q[b] = 1.0;
-            for i in [a, b] {
-                for j in 0..nm {
-                    if i != j && self.problem.musicians[i] == self.problem.musicians[j] {
-                        let qplus = 1.0 / self.place[i].distance(self.place[j]);
-                        self.q[i] += qplus;
-                        self.q[j] += qplus;
-                    }
-                }
-            }
-            // Consider double count a <=> b
-            if self.problem.musicians[a] == self.problem.musicians[b] {
-                let qplus = 1.0 / self.place[a].distance(self.place[b]);
-                self.q[a] -= qplus;
-                self.q[b] -= qplus;
-            }
-        }
-        self.update_score(None);
+impl Checkpoint {
+    pub fn path(name: &str, problem_id: ProblemId) -> PathBuf {
+        project_path(format!("checkpoint/{name}/{problem_id}.json"))
     }
 
-    #[allow(dead_code)]
-    fn assert_score(&self) {
-        let solution = self.to_solution();
-        let new_state = LocalState::new(self.problem, self.problem_id, self.spec, &solution);
-        assert_relative_eq!(self.score, new_state.score, max_relative = 1.0);
+    pub fn load(path: &Path) -> Result<Checkpoint> {
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("no checkpoint at {}", path.display()))?;
+        Ok(serde_json::from_str(&s)?)
     }
-}
 
-#[derive(Copy, Clone, derive_more::Display)]
-pub enum End {
-    #[display("iter-{}", "_0.to_string()")]
-    MaxIteration(usize),
-    #[display("duration-{}", "_0.as_secs()")]
-    MaxDuration(std::time::Duration),
+    fn save(&self, path: &Path) -> Result<()> {
+        // Write and rename, so that dying while saving keeps the last one.
+        let tmp = path.with_extension("json.tmp");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&tmp, serde_json::to_string(self)?)?;
+        Ok(std::fs::rename(tmp, path)?)
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -382,17 +119,28 @@ pub fn run_sa(
     solution: &Solution,
     temp0: Option<f64>,
     end: End,
-    sender: Option<async_channel::Sender<Solution>>,
+    mix: MoveMix,
+    shadows: Option<&ShadowMap>,
+    observer: &mut dyn ProgressObserver,
+    checkpoint_path: &Path,
+    checkpoint: Option<Checkpoint>,
+    cancel: &Cancel,
 ) -> Result<(Score, Solution)> {
-    let nm = problem.musicians.len();
-
+    let solution = match &checkpoint {
+        Some(checkpoint) => &checkpoint.solution,
+        None => solution,
+    };
     let mut st = LocalState::new(problem, problem_id, spec, solution);
 
     let mut sc = st.score;
 
     let timer = std::time::Instant::now();
 
-    let temp0 = temp0.unwrap_or_else(|| sc.abs() / (problem.musicians.len() as f64).sqrt());
+    let temp0 = match &checkpoint {
+        // Keep the schedule of the interrupted run.
+        Some(checkpoint) => checkpoint.temp0,
+        None => temp0.unwrap_or_else(|| sc.abs() / (problem.musicians.len() as f64).sqrt()),
+    };
     let mut temp = temp0;
 
     let mut best = sc;
@@ -405,143 +153,125 @@ pub fn run_sa(
 
     let mut ncollide = 0;
     let mut nmove = 0;
-    let sa_plot = project_path(format!("plot/sa/{}/{}.data", name, problem_id));
-    std::fs::create_dir_all(sa_plot.parent().unwrap())?;
-
-    let mut sa_plot = std::fs::File::create(sa_plot)?;
-    writeln!(
-        sa_plot,
-        "iteration score best temperature acceptrate acceptrate_positive acceptrate_negative"
-    )?;
 
+    let mut moves = MoveSelector::with_mix(mix);
     let mut niter = 0;
+    let mut elapsed0 = std::time::Duration::ZERO;
+    // A resumed run goes on with the move of the checkpointed iteration.
+    let mut resuming = checkpoint.is_some();
+
+    if let Some(checkpoint) = checkpoint {
+        info!(
+            "resuming from niter: {}, best: {:.1}",
+            checkpoint.niter, checkpoint.best
+        );
+        *rng = StdRng::from_seed(checkpoint.seed);
+        niter = checkpoint.niter;
+        elapsed0 = checkpoint.elapsed;
+        temp = checkpoint.temp;
+        sc = checkpoint.score;
+        best = checkpoint.best;
+        best_solution = checkpoint.best_solution;
+        moves = checkpoint.moves;
+        ncollide = checkpoint.ncollide;
+        nmove = checkpoint.nmove;
+    }
+    let start_iteration = niter;
+    let mut accept_rates = (0.0, 0.0, 0.0);
+
+    macro_rules! progress {
+        () => {
+            Progress {
+                name,
+                problem_id,
+                start_iteration,
+                iteration: niter,
+                elapsed: elapsed0 + timer.elapsed(),
+                score: sc,
+                best,
+                temperature: temp,
+                accept_rate: accept_rates.0,
+                accept_rate_positive: accept_rates.1,
+                accept_rate_negative: accept_rates.2,
+                ncollide,
+                nmove,
+                moves: Some(&moves),
+                state: &st,
+            }
+        };
+    }
 
     loop {
-        niter += 1;
+        if !resuming {
+            niter += 1;
+        }
 
-        if niter % 10 == 0
-            && let Some(sender) = &sender
-        {
-            sender.send_blocking(st.to_solution())?;
+        if niter % 10 == 0 {
+            observer.progress(&progress!())?;
         }
 
         if niter % 1_000 == 0 {
-            let done = match end {
-                End::MaxIteration(max_iter) => niter as f64 / max_iter as f64,
-                End::MaxDuration(max_duration) => {
-                    timer.elapsed().as_millis() as f64 / max_duration.as_millis() as f64
-                }
-            };
-            temp = temp0 * (1.0 - done);
+            temp = temp0 * (1.0 - end.done(niter, elapsed0 + timer.elapsed()));
 
-            if temp < 0.0 {
+            if temp < 0.0 || cancel.is_cancelled() {
+                observer.finish(&progress!())?;
                 return Ok((best, best_solution));
             }
         }
 
         if niter % 10_000 == 0 {
-            let accept_rate =
-                (naccept_positive + naccept_negative) as f64 / 1.0f64.max(ntotal as f64);
-            let accept_rate_positive = naccept_positive as f64 / 1.0f64.max(ntotal as f64);
-            let accept_rate_negative = naccept_negative as f64 / 1.0f64.max(ntotal as f64);
-            info!("temp: {temp:.1}, niter: {niter}, sc: {sc:.1}, best: {best:.1}, ncollide: {ncollide}, nmove: {nmove}, ntotal: {ntotal}, naccept_positive: {naccept_positive}, naccept_negative: {naccept_negative}, accept_rate: {accept_rate:.02}");
-
-            writeln!(
-                sa_plot,
-                "{} {:.1} {:.1} {:.1} {:.3} {:.3} {:.3}",
-                niter, sc, best, temp, accept_rate, accept_rate_positive, accept_rate_negative
-            )?;
+            let n = 1.0f64.max(ntotal as f64);
+            accept_rates = (
+                (naccept_positive + naccept_negative) as f64 / n,
+                naccept_positive as f64 / n,
+                naccept_negative as f64 / n,
+            );
             naccept_positive = 0;
             naccept_negative = 0;
             ntotal = 0;
         }
 
         if niter % 100_000 == 0 {
-            st.report_progress().unwrap();
-
             let solution = st.to_solution();
             st = LocalState::new(problem, problem_id, spec, &solution);
-        }
 
-        // Swap
-        if rng.random_range(0..10) == 0 {
-            let a = rng.random_range(0..nm);
-            let b = rng.random_range(0..nm);
-            if a == b {
-                continue;
-            }
-            st.do_swap(a, b);
-
-            let sc2 = st.score;
-            // let delta = sc2 - sc;
-
-            ntotal += 1;
-            if sc2 >= sc || ((sc2 - sc) / temp).exp() > rng.random_range(0.0..1.0) {
-                if sc2 >= sc {
-                    naccept_positive += 1;
-                } else {
-                    naccept_negative += 1;
-                }
-                sc = sc2;
-                if sc > best {
-                    best = sc;
-                    best_solution = st.to_solution();
+            if niter % CHECKPOINT_INTERVAL == 0 && !resuming {
+                let seed = rng.random();
+                *rng = StdRng::from_seed(seed);
+                Checkpoint {
+                    niter,
+                    elapsed: elapsed0 + timer.elapsed(),
+                    seed,
+                    temp0,
+                    temp,
+                    score: sc,
+                    solution,
+                    best,
+                    best_solution: best_solution.clone(),
+                    moves: moves.clone(),
+                    ncollide,
+                    nmove,
                 }
-            } else {
-                st.do_swap(a, b);
+                .save(checkpoint_path)?;
             }
-            continue;
         }
-
-        // Move
-        let id = rng.random_range(0..nm);
-        let p0 = st.place[id];
-
-        let collides = |p: Point| -> bool {
-            !st.problem.on_stage(p)
-                || (0..nm)
-                    .any(|j| id != j && p.distance_squared(st.place[j]) < MUSICIAN_RADIUS_2 + EPS)
-        };
-
-        let p = match rng.random_range(0..10) {
-            0 => problem.random_point_on_stage(rng),
-            1 => {
-                let dist = 40.0 * rng.random_range(0.0f64..1.0).powi(2);
-                let angle = rng.random_range(0.0f64..2.0 * std::f64::consts::PI);
-
-                let dx = angle.cos();
-                let dy = angle.sin();
-
-                let mut lo = 0.0;
-                let mut hi = dist;
-                while hi - lo > 1e-3 {
-                    let mi = (lo + hi) / 2.0;
-                    if collides(Point::new(p0.x + mi * dx, p0.y + mi + dy)) {
-                        hi = mi;
-                    } else {
-                        lo = mi;
-                    }
-                }
-                Point::new(p0.x + lo * dx, p0.y + lo * dy)
+        resuming = false;
+
+        let kind = moves.select(rng);
+        match apply(kind, &mut st, shadows, rng) {
+            Outcome::Applied => {}
+            Outcome::Collided => {
+                st.rollback();
+                ncollide += 1;
+                moves.collided(kind);
+                continue;
             }
-
-            // }
-            // 2 => {
-            //   // TODO: Gradient
-            // };
-            _ => {
-                let dist = 40.0 * rng.random_range(0.0f64..1.0).powi(2);
-                let angle = rng.random_range(0.0f64..2.0 * std::f64::consts::PI);
-                Point::new(p0.x + dist * angle.cos(), p0.y + dist * angle.sin())
+            Outcome::Wasted => {
+                moves.wasted(kind);
+                continue;
             }
-        };
-
-        if collides(p) {
-            ncollide += 1;
-            continue;
         }
         nmove += 1;
-        st.do_move(id, p);
 
         let sc2 = st.score;
         ntotal += 1;
@@ -552,13 +282,17 @@ pub fn run_sa(
             } else {
                 naccept_negative += 1;
             }
+            moves.evaluated(kind, sc2 - sc, true);
+            st.commit();
             sc = sc2;
             if sc > best {
                 best = sc;
                 best_solution = st.to_solution();
+                observer.new_best(problem_id, best, &best_solution)?;
             }
         } else {
-            st.do_move(id, p0);
+            moves.evaluated(kind, sc2 - sc, false);
+            st.rollback();
         }
     }
 }
@@ -568,17 +302,31 @@ pub struct SolverSa {
     problem: Problem,
     temp0: Option<f64>,
     end: End,
+    mix: MoveMix,
+    shadows: Option<ShadowMap>,
     initial_solution: Solution,
-    sender: Option<async_channel::Sender<Solution>>,
+    observers: Observers,
+    checkpoint: Option<Checkpoint>,
 }
 
 impl SolverSa {
-    fn initial_solution(problem: &Problem) -> Solution {
+    // The seed the runs table records. `solve` starts its rng from zeros.
+    pub const SEED: u64 = 0;
+
+    pub fn initial_solution(problem: &Problem) -> Solution {
+        Self::initial_solution_with(problem, None)
+    }
+
+    // With a shadow map, positions with clear sight lines are more likely.
+    pub fn initial_solution_with(problem: &Problem, shadows: Option<&ShadowMap>) -> Solution {
         let mut rng = SeedableRng::from_seed([0; 32]);
         let mut placements = vec![];
 
         while placements.len() < problem.musicians.len() {
-            let p = problem.random_point_on_stage(&mut rng);
+            let p = match shadows {
+                Some(map) => pillars::random_clear_point(problem, map, &mut rng),
+                None => problem.random_point_on_stage(&mut rng),
+            };
             if placements
                 .iter()
                 .all(|q| p.distance_squared(*q) > MUSICIAN_RADIUS_2 + EPS)
@@ -597,7 +345,7 @@ impl SolverSa {
         temp0: Option<f64>,
         end: End,
         initial_solution: Option<Solution>,
-        sender: Option<async_channel::Sender<Solution>>,
+        observers: Observers,
     ) -> Result<Self> {
         let problem = Problem::new(problem_id)?;
         let initial_solution = initial_solution.unwrap_or(Self::initial_solution(&problem));
@@ -606,10 +354,33 @@ impl SolverSa {
             problem,
             temp0,
             end,
+            mix: MoveMix::Default,
+            shadows: None,
             initial_solution,
-            sender,
+            observers,
+            checkpoint: None,
         })
     }
+
+    pub fn with_mix(mut self, mix: MoveMix) -> Self {
+        self.mix = mix;
+        self
+    }
+
+    // Teleports favour clear sight lines past the pillars.
+    pub fn with_shadows(mut self, shadows: Option<ShadowMap>) -> Self {
+        self.shadows = shadows;
+        self
+    }
+
+    // Continue from the last checkpoint of a run with the same parameters.
+    pub fn resume(mut self) -> Result<Self> {
+        self.checkpoint = Some(Checkpoint::load(&Checkpoint::path(
+            &self.name(),
+            self.problem_id,
+        ))?);
+        Ok(self)
+    }
 }
 
 impl Solver for SolverSa {
@@ -618,11 +389,23 @@ impl Solver for SolverSa {
     }
 
     fn name(&self) -> String {
-        format!("sa-temp0-{:.0}-{}", self.temp0.unwrap_or(0.0), self.end)
+        let mut name = format!("sa-temp0-{:.0}-{}", self.temp0.unwrap_or(0.0), self.end);
+        if self.mix != MoveMix::Default {
+            name = format!("{name}-{}", self.mix);
+        }
+        if self.shadows.is_some() {
+            name = format!("{name}-shadows");
+        }
+        name
     }
 
-    fn solve(&mut self) -> Result<Solved> {
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
         let mut rng = SeedableRng::from_seed([0; 32]);
+        let checkpoint_path = Checkpoint::path(&self.name(), self.problem_id);
 
         let (score, solution) = run_sa(
             &self.name(),
@@ -633,8 +416,32 @@ impl Solver for SolverSa {
             &self.initial_solution,
             self.temp0,
             self.end,
-            self.sender.clone(),
+            self.mix,
+            self.shadows.as_ref(),
+            &mut self.observers,
+            &checkpoint_path,
+            self.checkpoint.take(),
+            cancel,
         )?;
+
+        let mut st = LocalState::new(
+            &self.problem,
+            self.problem_id,
+            self.problem_id.into(),
+            &solution,
+        );
+        let blocker_gain = if cancel.is_cancelled() {
+            0.0
+        } else {
+            optimize_blockers(&mut st, &mut rng, BLOCKER_ITERATIONS)
+        };
+        let breakdown = st.breakdown(blocker_gain);
+        let (score, solution) = if blocker_gain > 0.0 {
+            (st.score, st.to_solution())
+        } else {
+            (score, solution)
+        };
+
         let Solution {
             placements,
             volumes,
@@ -645,26 +452,125 @@ impl Solver for SolverSa {
             score,
             placements,
             volumes,
+            breakdown: Some(breakdown),
         })
     }
+
+    fn seed(&self) -> u64 {
+        Self::SEED
+    }
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        Some(&self.initial_solution)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn end_from_str() -> Result<()> {
+        use std::time::Duration;
+        let duration = |s: &str| match s.parse::<End>() {
+            Ok(End::MaxDuration(d)) => Some(d),
+            _ => None,
+        };
+        assert_eq!(duration("1500ms"), Some(Duration::from_millis(1500)));
+        assert_eq!(duration("2m"), Some(Duration::from_secs(120)));
+        assert!(matches!("100".parse::<End>()?, End::MaxIteration(100)));
+        Ok(())
+    }
+
     #[test]
     #[ignore]
     fn solver_sa() -> Result<()> {
-        let cases = [(60, 34597619.50674734)];
+        let cases = [(60, 27946408.05360928)];
         for (id, score) in cases {
-            let mut solver = SolverSa::new(id, Some(100.0), End::MaxIteration(10_000), None, None)?;
-            let solved = solver.solve()?;
+            let mut solver = SolverSa::new(
+                id,
+                Some(100.0),
+                End::MaxIteration(10_000),
+                None,
+                Observers::default(),
+            )?;
+            let solved = solver.solve(&Cancel::new())?;
             assert_eq!(solved.score, score);
         }
         Ok(())
     }
 
+    // The run right after the checkpoint.
+    #[derive(Debug, PartialEq)]
+    struct Snapshot {
+        score: Score,
+        nmove: usize,
+        ncollide: usize,
+        placements: Vec<(Coord, Coord)>,
+        // Move statistics as JSON.
+        moves: String,
+    }
+
+    #[derive(Default)]
+    struct AfterCheckpoint(Option<Snapshot>);
+
+    impl ProgressObserver for AfterCheckpoint {
+        fn progress(&mut self, p: &Progress) -> Result<()> {
+            if p.iteration == CHECKPOINT_INTERVAL + 10 {
+                self.0 = Some(Snapshot {
+                    score: p.score,
+                    nmove: p.nmove,
+                    ncollide: p.ncollide,
+                    placements: p.state.place.iter().map(|q| (q.x, q.y)).collect(),
+                    moves: serde_json::to_string(p.moves.unwrap())?,
+                });
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn resume_from_checkpoint() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let dir = std::env::temp_dir().join(format!("resume-{}", std::process::id()));
+        let path = dir.join("0.json");
+        let end = End::MaxIteration(CHECKPOINT_INTERVAL + 200_000);
+        let run = |checkpoint, observer: &mut AfterCheckpoint| {
+            let mut rng = SeedableRng::from_seed([0; 32]);
+            run_sa(
+                "test-resume",
+                &mut rng,
+                &problem,
+                0,
+                Spec::V1,
+                &solution,
+                Some(100.0),
+                end,
+                MoveMix::Default,
+                None,
+                observer,
+                &path,
+                checkpoint,
+                &Cancel::new(),
+            )
+        };
+        let mut full_after = AfterCheckpoint::default();
+        let (score, full) = run(None, &mut full_after)?;
+        let mut resumed_after = AfterCheckpoint::default();
+        let resumed = run(Some(Checkpoint::load(&path)?), &mut resumed_after);
+        std::fs::remove_dir_all(&dir)?;
+        let (resumed_score, resumed) = resumed?;
+
+        assert!(full_after.0.is_some());
+        assert_eq!(full_after.0, resumed_after.0);
+        assert_eq!(score, resumed_score);
+        for (p, q) in full.placements.iter().zip(resumed.placements.iter()) {
+            assert_eq!((p.x, p.y), (q.x, q.y));
+        }
+        Ok(())
+    }
+
     #[test]
     fn sa_score_example_problem() -> Result<()> {
         let problem = Problem::example()?;
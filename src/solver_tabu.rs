@@ -0,0 +1,391 @@
+use crate::prelude::*;
+
+use crate::local_state::*;
+use crate::moves::two_classes;
+use crate::observer::*;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver::*;
+use crate::solver_sa::{End, SolverSa};
+
+// Tabu search over a fixed set of slots: the hex lattice plus the stage edges.
+// Each iteration samples relocations to free slots and swaps of musicians of
+// different instruments, and takes the best one that is not tabu. A tabu move
+// is still taken if it beats the best score so far.
+
+// Slots within this are sampled as nearby relocations.
+const NEAR_RADIUS: Coord = 3.0 * MUSICIAN_RADIUS;
+const RELOCATE_PROBABILITY: f64 = 0.8;
+const REBUILD_INTERVAL: usize = 10_000;
+
+struct Slots {
+    points: Vec<Point>,
+    // Slots too close to be occupied together, excluding the slot itself.
+    conflicts: Vec<Vec<usize>>,
+    near: Vec<Vec<usize>>,
+}
+
+impl Slots {
+    fn new(problem: &Problem) -> Slots {
+        let mut points = edge_slots(problem);
+        points.extend(candidate_slots(problem));
+
+        let cell = |p: Point| {
+            (
+                (p.x / NEAR_RADIUS).floor() as i64,
+                (p.y / NEAR_RADIUS).floor() as i64,
+            )
+        };
+        let mut grid = HashMap::<(i64, i64), Vec<usize>>::new();
+        for (s, p) in points.iter().enumerate() {
+            grid.entry(cell(*p)).or_default().push(s);
+        }
+
+        let mut conflicts = vec![vec![]; points.len()];
+        let mut near = vec![vec![]; points.len()];
+        for (s, p) in points.iter().enumerate() {
+            let (cx, cy) = cell(*p);
+            for gx in cx - 1..=cx + 1 {
+                for gy in cy - 1..=cy + 1 {
+                    for &t in grid.get(&(gx, gy)).into_iter().flatten() {
+                        if s == t {
+                            continue;
+                        }
+                        let d2 = p.distance_squared(points[t]);
+                        if d2 < MUSICIAN_RADIUS_2 + EPS {
+                            conflicts[s].push(t);
+                        }
+                        if d2 <= NEAR_RADIUS * NEAR_RADIUS {
+                            near[s].push(t);
+                        }
+                    }
+                }
+            }
+        }
+        Slots {
+            points,
+            conflicts,
+            near,
+        }
+    }
+}
+
+// Which musician is on which slot, and how many occupied slots conflict with
+// each slot, itself included.
+struct Occupancy {
+    slot_of: Vec<usize>,
+    load: Vec<u32>,
+}
+
+impl Occupancy {
+    fn occupy(&mut self, slots: &Slots, s: usize, delta: i32) {
+        for &t in std::iter::once(&s).chain(&slots.conflicts[s]) {
+            self.load[t] = self.load[t].checked_add_signed(delta).unwrap();
+        }
+    }
+
+    fn place(&mut self, slots: &Slots, i: usize, s: usize) {
+        self.occupy(slots, self.slot_of[i], -1);
+        self.slot_of[i] = s;
+        self.occupy(slots, s, 1);
+    }
+
+    // Whether musician i could move to slot s.
+    fn is_free_for(&self, slots: &Slots, i: usize, s: usize) -> bool {
+        let own = self.slot_of[i];
+        let own_load = u32::from(own == s || slots.conflicts[s].contains(&own));
+        self.load[s] == own_load
+    }
+}
+
+// Moves every musician to the nearest free slot, in order.
+fn snap(slots: &Slots, solution: &Solution) -> Result<(Occupancy, Solution)> {
+    let mut load = vec![0; slots.points.len()];
+    let mut slot_of = vec![];
+    for p in &solution.placements {
+        let s = (0..slots.points.len())
+            .filter(|s| load[*s] == 0)
+            .min_by_key(|s| OrderedFloat(slots.points[*s].distance_squared(*p)))
+            .context("no free slot")?;
+        for &t in std::iter::once(&s).chain(&slots.conflicts[s]) {
+            load[t] += 1;
+        }
+        slot_of.push(s);
+    }
+    let solution = Solution {
+        placements: slot_of.iter().map(|s| slots.points[*s]).collect(),
+        volumes: solution.volumes.clone(),
+    };
+    Ok((Occupancy { slot_of, load }, solution))
+}
+
+#[derive(Copy, Clone)]
+enum Move {
+    Relocate { i: usize, s: usize },
+    Swap { a: usize, b: usize },
+}
+
+pub struct SolverTabu {
+    problem_id: ProblemId,
+    problem: Problem,
+    tenure: usize,
+    neighborhood: usize,
+    end: End,
+    initial_solution: Solution,
+    observers: Observers,
+}
+
+impl SolverTabu {
+    pub fn new(
+        problem_id: ProblemId,
+        tenure: usize,
+        neighborhood: usize,
+        end: End,
+        initial_solution: Option<Solution>,
+        observers: Observers,
+    ) -> Result<Self> {
+        ensure!(neighborhood > 0, "empty neighborhood");
+        let problem = Problem::new(problem_id)?;
+        let initial_solution =
+            initial_solution.unwrap_or_else(|| SolverSa::initial_solution(&problem));
+        Ok(Self {
+            problem_id,
+            problem,
+            tenure,
+            neighborhood,
+            end,
+            initial_solution,
+            observers,
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run_tabu(
+    name: &str,
+    rng: &mut StdRng,
+    problem: &Problem,
+    problem_id: ProblemId,
+    spec: Spec,
+    solution: &Solution,
+    tenure: usize,
+    neighborhood: usize,
+    end: End,
+    observer: &mut dyn ProgressObserver,
+    cancel: &Cancel,
+) -> Result<(Score, Solution)> {
+    let slots = Slots::new(problem);
+    let input = LocalState::new(problem, problem_id, spec, solution).score;
+    let (mut occupancy, snapped) = snap(&slots, solution)?;
+    let mut st = LocalState::new(problem, problem_id, spec, &snapped);
+    info!("tabu: snapped to slots: {input:.1} -> {:.1}", st.score);
+    st.rebase_score();
+    // Snapping can lose a lot, so the input stays the best until beaten.
+    let (mut best, mut best_solution) = if input > st.score {
+        (input, solution.clone())
+    } else {
+        (st.score, st.to_solution())
+    };
+
+    // (musician, slot) -> the iteration until which the musician may not
+    // return to the slot.
+    let mut tabu = HashMap::<(usize, usize), usize>::new();
+    let timer = std::time::Instant::now();
+    let mut niter = 0;
+    let mut nmove = 0;
+
+    macro_rules! progress {
+        () => {
+            Progress {
+                name,
+                problem_id,
+                start_iteration: 0,
+                iteration: niter,
+                elapsed: timer.elapsed(),
+                score: st.score,
+                best,
+                temperature: 0.0,
+                accept_rate: 0.0,
+                accept_rate_positive: 0.0,
+                accept_rate_negative: 0.0,
+                ncollide: 0,
+                nmove,
+                moves: None,
+                state: &st,
+            }
+        };
+    }
+
+    loop {
+        niter += 1;
+        if end.done(niter, timer.elapsed()) >= 1.0 || cancel.is_cancelled() {
+            info!("tabu: niter: {niter}, best: {best:.1}");
+            observer.finish(&progress!())?;
+            return Ok((best, best_solution));
+        }
+        if niter % 10 == 0 {
+            observer.progress(&progress!())?;
+        }
+        if niter % REBUILD_INTERVAL == 0 {
+            info!("tabu: niter: {niter}, sc: {:.1}, best: {best:.1}", st.score);
+            st = LocalState::new(problem, problem_id, spec, &st.to_solution());
+            st.rebase_score();
+            tabu.retain(|_, until| *until > niter);
+        }
+
+        let is_tabu = |i: usize, s: usize| tabu.get(&(i, s)).is_some_and(|until| *until > niter);
+        let sc = st.score;
+        let mut chosen: Option<(Score, Move)> = None;
+        for _ in 0..neighborhood {
+            let (delta, tabu_move, mv) = if rng.random_bool(RELOCATE_PROBABILITY) {
+                let i = rng.random_range(0..st.place.len());
+                let near = &slots.near[occupancy.slot_of[i]];
+                let s = if rng.random_bool(0.5) && !near.is_empty() {
+                    near[rng.random_range(0..near.len())]
+                } else {
+                    rng.random_range(0..slots.points.len())
+                };
+                // Staying put is not a move.
+                if s == occupancy.slot_of[i] || !occupancy.is_free_for(&slots, i, s) {
+                    continue;
+                }
+                let delta = st.delta_move(i, slots.points[s]);
+                (delta, is_tabu(i, s), Move::Relocate { i, s })
+            } else {
+                let Some((ca, cb)) = two_classes(&st, rng) else {
+                    continue;
+                };
+                let a = st.classes[ca][rng.random_range(0..st.classes[ca].len())];
+                let b = st.classes[cb][rng.random_range(0..st.classes[cb].len())];
+                st.swap(a, b);
+                let delta = st.score - sc;
+                st.rollback();
+                let (sa, sb) = (occupancy.slot_of[a], occupancy.slot_of[b]);
+                (delta, is_tabu(a, sb) || is_tabu(b, sa), Move::Swap { a, b })
+            };
+            // Aspiration: a tabu move is fine if it gives a new best.
+            if tabu_move && sc + delta <= best {
+                continue;
+            }
+            if chosen.is_none_or(|(d, _)| delta > d) {
+                chosen = Some((delta, mv));
+            }
+        }
+
+        let Some((_, mv)) = chosen else {
+            continue;
+        };
+        let until = niter + tenure + rng.random_range(0..=tenure / 2);
+        match mv {
+            Move::Relocate { i, s } => {
+                tabu.insert((i, occupancy.slot_of[i]), until);
+                occupancy.place(&slots, i, s);
+                st.move_to(i, slots.points[s]);
+            }
+            Move::Swap { a, b } => {
+                let (sa, sb) = (occupancy.slot_of[a], occupancy.slot_of[b]);
+                tabu.insert((a, sa), until);
+                tabu.insert((b, sb), until);
+                // Musicians swap places, so the set of occupied slots stays.
+                occupancy.slot_of[a] = sb;
+                occupancy.slot_of[b] = sa;
+                st.swap(a, b);
+            }
+        }
+        st.commit();
+        nmove += 1;
+
+        if st.score > best {
+            best = st.score;
+            best_solution = st.to_solution();
+            debug!("tabu: new best: {best:.1}");
+            observer.new_best(problem_id, best, &best_solution)?;
+        }
+    }
+}
+
+impl Solver for SolverTabu {
+    fn problem_id(&self) -> ProblemId {
+        self.problem_id
+    }
+
+    fn name(&self) -> String {
+        format!("tabu-t{}-n{}-{}", self.tenure, self.neighborhood, self.end)
+    }
+
+    fn solve(&mut self, cancel: &Cancel) -> Result<Solved> {
+        let mut rng = SeedableRng::from_seed([0; 32]);
+
+        let (score, solution) = run_tabu(
+            &self.name(),
+            &mut rng,
+            &self.problem,
+            self.problem_id,
+            self.problem_id.into(),
+            &self.initial_solution,
+            self.tenure,
+            self.neighborhood,
+            self.end,
+            &mut self.observers,
+            cancel,
+        )?;
+        let Solution {
+            placements,
+            volumes,
+        } = solution;
+        Ok(Solved {
+            problem_id: self.problem_id,
+            solver_name: self.name(),
+            score,
+            placements,
+            volumes,
+            breakdown: None,
+        })
+    }
+
+    fn observe(&mut self, observer: Box<dyn ProgressObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    fn seed(&self) -> u64 {
+        0
+    }
+
+    fn initial_solution(&self) -> Option<&Solution> {
+        Some(&self.initial_solution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tabu_example_problem() -> Result<()> {
+        let problem = Problem::example()?;
+        let solution = Solution::example()?;
+        let initial = crate::solver_sa::score(&problem, 0, Spec::V1, &solution);
+        let mut rng = StdRng::seed_from_u64(0);
+        let (score, solution) = run_tabu(
+            "test-tabu",
+            &mut rng,
+            &problem,
+            0,
+            Spec::V1,
+            &solution,
+            10,
+            16,
+            End::MaxIteration(300),
+            &mut Observers::default(),
+            &Cancel::new(),
+        )?;
+        solution.validate(&problem)?;
+        assert!(score >= initial);
+        assert_relative_eq!(
+            score,
+            crate::solver_sa::score(&problem, 0, Spec::V1, &solution),
+            max_relative = 1e-9
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use crate::prelude::*;
+
+use crate::db::{self, StoredSolution};
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver_sa;
+use sha2::{Digest, Sha256};
+use std::time::SystemTime;
+
+// Solutions in the database, keyed by the SHA-256 of their JSON, so that the
+// same solution is kept once however often it is saved. solution/best and
+// solution/submission can be written from it: the best valid solution, and
+// the one submitted last.
+
+// The key and the body.
+pub fn content(solution: &Solution) -> Result<(String, Vec<u8>)> {
+    let body = serde_json::to_vec(solution)?;
+    let hash = Sha256::digest(&body)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    Ok((hash, body))
+}
+
+// Keeps the solution, checked against the problem, and returns its hash.
+pub fn put(
+    problem: &Problem,
+    problem_id: ProblemId,
+    score: Score,
+    solution: &Solution,
+    provenance: &str,
+) -> Result<String> {
+    let (hash, body) = content(solution)?;
+    db::insert_solution(&StoredSolution {
+        hash: hash.clone(),
+        problem_id,
+        score,
+        valid: solution.validate(problem).is_ok(),
+        provenance: provenance.to_string(),
+        created: db::unix_time(SystemTime::now()),
+        submitted: None,
+        body,
+    })?;
+    Ok(hash)
+}
+
+pub fn get(hash: &str) -> Result<Option<Solution>> {
+    db::solution(hash)?
+        .map(|stored| Ok(serde_json::from_slice(&stored.body)?))
+        .transpose()
+}
+
+pub fn read(path: &Path) -> Result<Solution> {
+    let s = std::fs::read_to_string(path)?;
+    serde_json::from_str(&s).with_context(|| format!("invalid {}", path.display()))
+}
+
+// The files under solution/ for a problem, with their provenance: the solver
+// in the name under solution/all, and the directory elsewhere.
+fn files(problem_id: ProblemId) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Solution::stored(problem_id)?
+        .into_iter()
+        .map(|(solver, _, path)| (solver, path))
+        .collect::<Vec<_>>();
+    for entry in std::fs::read_dir(project_path("solution"))? {
+        let dir = entry?.path();
+        if !dir.is_dir() || dir.ends_with("all") {
+            continue;
+        }
+        let path = dir.join(format!("{problem_id}.json"));
+        if path.exists() {
+            let name = dir.file_name().unwrap().to_string_lossy().to_string();
+            files.push((name, path));
+        }
+    }
+    Ok(files)
+}
+
+// Imports the files under solution/, rescored. Those in solution/submission
+// count as submitted when they were last modified.
+pub fn import(ids: &[ProblemId]) -> Result<()> {
+    for &id in ids {
+        let problem = Problem::new(id)?;
+        let files = files(id)?;
+        for (provenance, path) in &files {
+            let solution = match read(path) {
+                Ok(solution) => solution,
+                Err(e) => {
+                    warn!("import: {e:?}");
+                    continue;
+                }
+            };
+            let score = solver_sa::score(&problem, id, id.into(), &solution);
+            let hash = put(&problem, id, score, &solution, provenance)?;
+            if provenance == "submission" {
+                let modified = std::fs::metadata(path)?.modified()?;
+                db::mark_submitted(&hash, db::unix_time(modified))?;
+            }
+        }
+        println!("{id}: {} files", files.len());
+    }
+    Ok(())
+}
+
+// Keeps the file as submitted now.
+pub fn mark_submitted(problem_id: ProblemId, path: &Path) -> Result<()> {
+    let problem = Problem::new(problem_id)?;
+    let solution = read(path)?;
+    let score = solver_sa::score(&problem, problem_id, problem_id.into(), &solution);
+    let hash = put(&problem, problem_id, score, &solution, "submission")?;
+    db::mark_submitted(&hash, db::unix_time(SystemTime::now()))?;
+    println!("{problem_id}: {hash} submitted");
+    Ok(())
+}
+
+fn export(dir: &str, problem_id: ProblemId, stored: Option<StoredSolution>) -> Result<()> {
+    let Some(stored) = stored else {
+        println!("{problem_id}: nothing stored");
+        return Ok(());
+    };
+    write_to(
+        format!("solution/{dir}/{problem_id}.json"),
+        std::str::from_utf8(&stored.body)?,
+    )?;
+    println!(
+        "{problem_id}: {:.1} {} {}",
+        stored.score, stored.provenance, stored.hash
+    );
+    Ok(())
+}
+
+// solution/best from the best valid solutions. The best scores follow if
+// they were behind.
+pub fn export_best(ids: &[ProblemId]) -> Result<()> {
+    for &id in ids {
+        let stored = db::best_solution(id)?;
+        if let Some(stored) = &stored
+            && db::score(id)?.is_none_or(|best| best < stored.score)
+        {
+            db::update_score(id, stored.score)?;
+        }
+        export("best", id, stored)?;
+    }
+    Ok(())
+}
+
+// solution/submission from the solutions submitted last.
+pub fn export_submission(ids: &[ProblemId]) -> Result<()> {
+    for &id in ids {
+        export("submission", id, db::submitted_solution(id)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_addressed() -> Result<()> {
+        let problem = Problem::example()?;
+        let mut solution = Solution::example()?;
+        // Tests start from an empty database, see db.rs.
+        assert!(db::solution(&content(&solution)?.0)?.is_none());
+        let hash = put(&problem, 0, 1.0, &solution, "test")?;
+        assert_eq!(hash.len(), 64);
+        // The same content is the same solution.
+        assert_eq!(put(&problem, 0, 1.0, &solution, "other")?, hash);
+        assert_eq!(db::solution(&hash)?.unwrap().provenance, "test");
+        assert_eq!(content(&get(&hash)?.unwrap())?.0, hash);
+
+        solution.volumes[0] = 2.0;
+        assert_ne!(content(&solution)?.0, hash);
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use crate::prelude::*;
+
+use crate::elite;
+use crate::local_state::*;
+use crate::moves::two_classes;
+use crate::pillars;
+use crate::problem::*;
+use crate::solution::*;
+use crate::solver_sa::{self, SolverSa};
+
+// Problems with the same stage size can share layouts. A source's best or elite
+// placements are moved onto the target's stage and the target's musicians are
+// assigned to them: the most valuable (position, instrument) pairs first, then
+// improved by swaps under the exact score. Missing positions come from the
+// lattice, and the least valuable extra positions are left out.
+
+// Random swaps tried after the greedy assignment.
+const SWAP_TRIES: usize = 5_000;
+
+pub fn same_stage(a: &Problem, b: &Problem) -> bool {
+    (a.stage_width - b.stage_width).abs() < EPS && (a.stage_height - b.stage_height).abs() < EPS
+}
+
+// The other problems whose stage is the same size.
+pub fn similar(problem: &Problem, problem_id: ProblemId) -> Vec<ProblemId> {
+    (1..=MAX_PROMLEM_ID)
+        .filter(|id| *id != problem_id)
+        .filter(|id| Problem::new(*id).is_ok_and(|other| same_stage(problem, &other)))
+        .collect()
+}
+
+pub fn transfer(
+    problem: &Problem,
+    problem_id: ProblemId,
+    source: &Problem,
+    solution: &Solution,
+) -> Option<Solution> {
+    let nm = problem.musicians.len();
+    let [dx, dy] = [
+        problem.stage_bottom_left[0] - source.stage_bottom_left[0],
+        problem.stage_bottom_left[1] - source.stage_bottom_left[1],
+    ];
+    let mut positions = solution
+        .placements
+        .iter()
+        .map(|p| Point::new(p.x + dx, p.y + dy))
+        .filter(|p| problem.on_stage(*p))
+        .collect::<Vec<_>>();
+    if positions.len() < nm {
+        let free = candidate_slots(problem).into_iter().filter(|p| {
+            positions
+                .iter()
+                .all(|q| p.distance_squared(*q) >= MUSICIAN_RADIUS_2 + EPS)
+        });
+        let free = free.take(nm - positions.len()).collect::<Vec<_>>();
+        positions.extend(free);
+        if positions.len() < nm {
+            return None;
+        }
+    }
+
+    let impacts = positions
+        .iter()
+        .map(|p| pillars::impacts(problem, *p))
+        .collect::<Vec<_>>();
+    let mut pairs = inst_cnt(&problem.musicians)
+        .keys()
+        .flat_map(|inst| (0..positions.len()).map(move |j| (*inst, j)))
+        .collect::<Vec<_>>();
+    pairs.sort_by_key(|(inst, j)| std::cmp::Reverse(OrderedFloat(impacts[*j][*inst])));
+    let mut musicians = HashMap::<Instrument, Vec<usize>>::new();
+    for (i, inst) in problem.musicians.iter().enumerate().rev() {
+        musicians.entry(*inst).or_default().push(i);
+    }
+    let mut placements = vec![Point::new(0.0, 0.0); nm];
+    let mut taken = vec![false; positions.len()];
+    for (inst, j) in pairs {
+        if taken[j] {
+            continue;
+        }
+        if let Some(i) = musicians.get_mut(&inst).unwrap().pop() {
+            placements[i] = positions[j];
+            taken[j] = true;
+        }
+    }
+    let solution = Solution {
+        placements,
+        volumes: vec![10.0; nm],
+    };
+    solution.validate(problem).ok()?;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut st = LocalState::new(problem, problem_id, problem_id.into(), &solution);
+    // Swaps are scored at the best volumes.
+    st.rebase_score();
+    for _ in 0..SWAP_TRIES {
+        let Some((ca, cb)) = two_classes(&st, &mut rng) else {
+            break;
+        };
+        let a = st.classes[ca][rng.random_range(0..st.classes[ca].len())];
+        let b = st.classes[cb][rng.random_range(0..st.classes[cb].len())];
+        let sc = st.score;
+        st.swap(a, b);
+        if st.score > sc {
+            st.commit();
+        } else {
+            st.rollback();
+        }
+    }
+    Some(st.to_solution())
+}
+
+// The best of the similar problems' solutions on this problem, with its score
+// and where it came from. Each source gives its best and a random member of
+// its elite pool.
+pub fn best_transfer(
+    problem: &Problem,
+    problem_id: ProblemId,
+    rng: &mut StdRng,
+) -> Result<Option<(Score, ProblemId, Solution)>> {
+    let mut best: Option<(Score, ProblemId, Solution)> = None;
+    for source_id in similar(problem, problem_id) {
+        let candidates = Solution::best(source_id)
+            .ok()
+            .into_iter()
+            .chain(elite::sample(source_id, rng)?);
+        let source = Problem::new(source_id)?;
+        for solution in candidates {
+            let Some(solution) = transfer(problem, problem_id, &source, &solution) else {
+                continue;
+            };
+            let score = LocalState::new(problem, problem_id, problem_id.into(), &solution).score;
+            info!("transfer: {source_id} -> {problem_id}: {score:.1}");
+            if best.as_ref().is_none_or(|(s, _, _)| score > *s) {
+                best = Some((score, source_id, solution));
+            }
+        }
+    }
+    Ok(best)
+}
+
+// Transfers onto problem_id and saves the result to solution/transfer/ if it
+// beats the random start, for --initial-solution-path.
+pub fn run(problem_id: ProblemId) -> Result<()> {
+    let problem = Problem::new(problem_id)?;
+    let similar = similar(&problem, problem_id);
+    println!("{problem_id}: similar: {similar:?}");
+    let random = SolverSa::initial_solution(&problem);
+    let random_score = solver_sa::score(&problem, problem_id, problem_id.into(), &random);
+    let Some((score, source_id, solution)) =
+        best_transfer(&problem, problem_id, &mut StdRng::seed_from_u64(0))?
+    else {
+        println!("{problem_id}: nothing to transfer");
+        return Ok(());
+    };
+    println!("{problem_id}: from {source_id}: {score:.1}, random: {random_score:.1}");
+    if score > random_score {
+        let path = format!("solution/transfer/{problem_id}.json");
+        write_to(&path, &serde_json::to_string(&solution)?)?;
+        println!("{problem_id}: saved {path}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_repairs_counts() -> Result<()> {
+        let source = Problem::example()?;
+        let solution = Solution::example()?;
+
+        // One more musician on a shifted stage.
+        let mut problem = source.clone();
+        problem.musicians.push(1);
+        problem.stage_bottom_left = [300.0, 100.0];
+        problem.pillars.clear();
+        assert!(same_stage(&problem, &source));
+        let transferred = transfer(&problem, 0, &source, &solution).unwrap();
+        transferred.validate(&problem)?;
+
+        // Fewer musicians: the extra positions are left out.
+        problem.musicians = vec![1];
+        let transferred = transfer(&problem, 0, &source, &solution).unwrap();
+        transferred.validate(&problem)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use crate::prelude::*;
+
+use crate::moves::MoveMix;
+use crate::observer::Observers;
+use crate::problem::*;
+use crate::solver::{Cancel, Solver};
+use crate::solver_sa::{End, SolverSa};
+use rand::seq::SliceRandom;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Races SA configurations over a sample of problems per size class. Every
+// configuration runs on the problems one after another, from the same start
+// and for the same short budget. After each problem, a configuration that
+// another one beat on every problem so far is dropped. The survivor with the
+// best mean rank is written to the solver config file, whose params `solve`
+// uses unless given others with --param. Only temp0 and the move mix are
+// raced: the duration is fixed by --budget and not tuned.
+
+pub const CONFIG_PATH: &str = "solver/config.json";
+
+const TEMP0S: [f64; 5] = [0.0, 10.0, 100.0, 1_000.0, 10_000.0];
+const MIXES: [MoveMix; 3] = [MoveMix::Default, MoveMix::Local, MoveMix::Global];
+// Nobody is dropped before this many problems.
+const MIN_PROBLEMS: usize = 2;
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, derive_more::Display,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeClass {
+    #[display("small")]
+    Small,
+    #[display("medium")]
+    Medium,
+    #[display("large")]
+    Large,
+}
+
+impl SizeClass {
+    // By musicians * attendees, which is what an SA iteration costs.
+    pub fn of(problem: &Problem) -> SizeClass {
+        match problem.musicians.len() * problem.attendees.len() {
+            0..20_000 => SizeClass::Small,
+            20_000..200_000 => SizeClass::Medium,
+            _ => SizeClass::Large,
+        }
+    }
+}
+
+// Params by solver and size class, as they would be given with --param.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct SolverConfig(BTreeMap<String, BTreeMap<SizeClass, BTreeMap<String, String>>>);
+
+impl SolverConfig {
+    // Empty if there is no config file.
+    pub fn load() -> Result<SolverConfig> {
+        if !project_path(CONFIG_PATH).exists() {
+            return Ok(SolverConfig::default());
+        }
+        let s = read_from(CONFIG_PATH)?;
+        serde_json::from_str(&s).with_context(|| format!("invalid {CONFIG_PATH}"))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_to(CONFIG_PATH, &serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn params(&self, solver: &str, class: SizeClass) -> Vec<(String, String)> {
+        self.0
+            .get(solver)
+            .and_then(|classes| classes.get(&class))
+            .map(|params| params.clone().into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, solver: &str, class: SizeClass, params: Vec<(String, String)>) {
+        self.0
+            .entry(solver.to_string())
+            .or_default()
+            .insert(class, params.into_iter().collect());
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Config {
+    // 0 derives it from the initial score.
+    temp0: f64,
+    mix: MoveMix,
+}
+
+impl Config {
+    fn all() -> Vec<Config> {
+        TEMP0S
+            .iter()
+            .flat_map(|&temp0| MIXES.iter().map(move |&mix| Config { temp0, mix }))
+            .collect()
+    }
+
+    fn params(&self) -> Vec<(String, String)> {
+        vec![
+            ("temp0".to_string(), self.temp0.to_string()),
+            ("mix".to_string(), self.mix.to_string()),
+        ]
+    }
+
+    fn run(&self, id: ProblemId, budget: Duration, cancel: &Cancel) -> Result<Score> {
+        let mut solver = SolverSa::new(
+            id,
+            (self.temp0 > 0.0).then_some(self.temp0),
+            End::MaxDuration(budget),
+            None,
+            Observers::default(),
+        )?
+        .with_mix(self.mix);
+        // Racing runs are only compared, not saved.
+        Ok(solver.solve(cancel)?.score)
+    }
+}
+
+// Whether a beat b on every problem so far.
+fn dominates(a: &[Score], b: &[Score]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a > b)
+}
+
+// The mean over problems of each configuration's rank among `alive`, 0 being
+// the best.
+fn mean_ranks(scores: &[Vec<Score>], alive: &[usize]) -> Vec<f64> {
+    let mut ranks = vec![0.0; scores.len()];
+    for &c in alive {
+        let nproblem = scores[c].len() as f64;
+        for (k, score) in scores[c].iter().enumerate() {
+            let rank = alive.iter().filter(|d| scores[**d][k] > *score).count();
+            ranks[c] += rank as f64 / nproblem;
+        }
+    }
+    ranks
+}
+
+// Runs the configurations on each problem in turn, `workers` at a time.
+fn race(
+    ids: &[ProblemId],
+    configs: &[Config],
+    budget: Duration,
+    workers: usize,
+    cancel: &Cancel,
+) -> Result<usize> {
+    let mut scores = vec![vec![]; configs.len()];
+    let mut alive = (0..configs.len()).collect::<Vec<_>>();
+    for (k, &id) in ids.iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let queue = Mutex::new(alive.iter().copied().collect::<VecDeque<_>>());
+        let results = Mutex::new(vec![]);
+        std::thread::scope(|s| {
+            for _ in 0..workers.min(alive.len()) {
+                s.spawn(|| {
+                    while let Some(c) = queue.lock().unwrap().pop_front() {
+                        let score = configs[c].run(id, budget, cancel).unwrap_or_else(|e| {
+                            error!("tune: {id}: {:?}: {e:?}", configs[c]);
+                            Score::NEG_INFINITY
+                        });
+                        results.lock().unwrap().push((c, score));
+                    }
+                });
+            }
+        });
+        for (c, score) in results.into_inner().unwrap() {
+            scores[c].push(score);
+        }
+
+        if k + 1 >= MIN_PROBLEMS {
+            let before = alive.clone();
+            alive.retain(|c| !before.iter().any(|d| dominates(&scores[*d], &scores[*c])));
+        }
+        info!("tune: {id}: {} of {} left", alive.len(), configs.len());
+    }
+    ensure!(
+        !scores[alive[0]].is_empty(),
+        "cancelled before the first run"
+    );
+
+    let ranks = mean_ranks(&scores, &alive);
+    for &c in &alive {
+        println!(
+            "{:>8} {:>8} {:>6.2}",
+            configs[c].temp0, configs[c].mix, ranks[c]
+        );
+    }
+    Ok(*alive
+        .iter()
+        .min_by_key(|c| OrderedFloat(ranks[**c]))
+        .unwrap())
+}
+
+// Samples `sample` problems of each size class among `ids`, and races on them.
+pub fn tune(
+    ids: &[ProblemId],
+    sample: usize,
+    budget: Duration,
+    workers: usize,
+    seed: u64,
+    cancel: &Cancel,
+) -> Result<()> {
+    ensure!(workers > 0, "workers must be positive");
+    let mut classes = BTreeMap::<SizeClass, Vec<ProblemId>>::new();
+    for &id in ids {
+        classes
+            .entry(SizeClass::of(&Problem::new(id)?))
+            .or_default()
+            .push(id);
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let configs = Config::all();
+    let mut config = SolverConfig::load()?;
+    for (class, mut ids) in classes {
+        ids.shuffle(&mut rng);
+        ids.truncate(sample);
+        println!("{class}: {ids:?}");
+        let winner = configs[race(&ids, &configs, budget, workers, cancel)?];
+        println!("{class}: temp0: {}, mix: {}", winner.temp0, winner.mix);
+        if cancel.is_cancelled() {
+            break;
+        }
+        config.set("sa", class, winner.params());
+        config.save()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn race_drops_dominated() {
+        let scores = vec![vec![3.0, 3.0], vec![1.0, 4.0], vec![2.0, 2.0]];
+        assert!(dominates(&scores[0], &scores[2]));
+        assert!(!dominates(&scores[0], &scores[1]));
+        let ranks = mean_ranks(&scores, &[0, 1]);
+        assert_eq!((ranks[0], ranks[1]), (0.5, 0.5));
+
+        let config: SolverConfig =
+            serde_json::from_str(r#"{"sa": {"small": {"temp0": "10"}}}"#).unwrap();
+        assert_eq!(
+            config.params("sa", SizeClass::Small),
+            [("temp0".to_string(), "10".to_string())]
+        );
+        assert!(config.params("sa", SizeClass::Large).is_empty());
+    }
+}